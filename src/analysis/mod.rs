@@ -0,0 +1,487 @@
+//! Static analysis of ROMs: follows the control flow from 0x200 to tell code from data,
+//! disassembles the instructions and flags what would go wrong when running them.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+const START: u16 = 0x200; // where ROMs are loaded and start running
+const RAM_SIZE: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning, // allowed, but often a mistake
+    Error,   // breaks or crashes when it runs
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// Something wrong with the instruction at `address`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Problem {
+    pub address: u16,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// What the analysis found out about a ROM.
+pub struct Analysis {
+    rom_end: usize,                                // first address after the ROM
+    pub instructions: BTreeMap<u16, u16>,          // reachable instructions by address
+    pub successors: BTreeMap<u16, BTreeSet<u16>>,  // where each instruction continues after it
+    pub subroutines: BTreeMap<u16, BTreeSet<u16>>, // called addresses with the addresses calling them
+    pub problems: Vec<Problem>,                    // ordered by address
+}
+
+// Where the walk is: the address, the subroutine it is in (None for the main program) and
+// the value of I if it is known
+type State = (u16, Option<u16>, Option<u16>);
+
+impl Analysis {
+    /// Analyzes a ROM as loaded at 0x200.
+    pub fn new(rom: &[u8]) -> Analysis {
+        let mut ram = [0u8; RAM_SIZE];
+        let rom_end = (START as usize + rom.len()).min(RAM_SIZE);
+        ram[START as usize..rom_end].copy_from_slice(&rom[..rom_end - START as usize]);
+
+        let mut analysis = Analysis {
+            rom_end,
+            instructions: BTreeMap::new(),
+            successors: BTreeMap::new(),
+            subroutines: BTreeMap::new(),
+            problems: Vec::new(),
+        };
+        let mut problems = BTreeSet::new();
+        let mut returns = BTreeSet::new(); // subroutines that return
+        let mut waiting: BTreeMap<u16, Vec<(u16, State)>> = BTreeMap::new(); // calls by subroutine
+        let mut visited = BTreeSet::new();
+        let mut pending: Vec<(u16, State)> = vec![(START, (START, None, None))]; // with where from
+
+        while let Some((from, state)) = pending.pop() {
+            if !visited.insert(state) {
+                continue;
+            }
+            let (address, subroutine, i) = state;
+            if address as usize + 1 >= rom_end {
+                problems.insert(Problem {
+                    address: from,
+                    severity: Severity::Error,
+                    message: "Execution runs past the end of the ROM".to_string(),
+                });
+                continue;
+            }
+
+            let instruction =
+                (ram[address as usize] as u16) << 8 | ram[address as usize + 1] as u16;
+            analysis.instructions.insert(address, instruction);
+            let mut report = |severity, message: String| {
+                problems.insert(Problem {
+                    address,
+                    severity,
+                    message,
+                });
+            };
+
+            let nnn = instruction & 0x0FFF;
+            let x = (instruction >> 8 & 0xF) as usize;
+            let mut next_i = i;
+            let mut targets = Vec::new(); // jumps, checked before they are followed
+            let mut next = vec![address + 2];
+            let mut called = None; // subroutine called here
+            let mut returned = None; // subroutine returned from here
+            match instruction >> 12 {
+                0x0 if instruction == 0x00EE => {
+                    next.clear();
+                    match subroutine {
+                        Some(subroutine) => returned = Some(subroutine),
+                        None => report(
+                            Severity::Error,
+                            "Return (00EE) outside of a subroutine".to_string(),
+                        ),
+                    }
+                }
+                0x0 if instruction == 0x00E0 => (),
+                0x0 => report(
+                    Severity::Warning,
+                    format!("Machine code call {:04X} is ignored", instruction),
+                ),
+                0x1 => {
+                    next.clear();
+                    targets.push(nnn);
+                }
+                0x2 => {
+                    next.clear();
+                    if Analysis::check_target(&mut report, nnn, "Call", rom_end) {
+                        analysis.subroutines.entry(nnn).or_default().insert(address);
+                        pending.push((address, (nnn, Some(nnn), i)));
+                        called = Some(nnn);
+                    }
+                }
+                0x3 | 0x4 => next.push(address + 4),
+                0x5 | 0x9 if instruction & 0xF == 0 => next.push(address + 4),
+                0x6 | 0x7 | 0xC => (),
+                0x8 if matches!(instruction & 0xF, 0x0..=0x7 | 0xE) => (),
+                0xA => next_i = Some(nnn),
+                0xB => {
+                    next.clear();
+                    report(
+                        Severity::Warning,
+                        format!(
+                            "Computed jump to {:#05X} + V0 is not followed, code there may be taken for data",
+                            nnn
+                        ),
+                    );
+                }
+                0xD => Analysis::check_range(&mut report, i, instruction & 0xF, "Sprite"),
+                0xE if matches!(instruction & 0xFF, 0x9E | 0xA1) => next.push(address + 4),
+                0xF => match instruction & 0xFF {
+                    0x07 | 0x0A | 0x15 | 0x18 => (),
+                    0x1E | 0x29 => next_i = None,
+                    0x33 => Analysis::check_range(&mut report, i, 3, "Fx33 write"),
+                    0x55 | 0x65 => {
+                        let name = if instruction & 0xFF == 0x55 {
+                            "Fx55 write"
+                        } else {
+                            "Fx65 read"
+                        };
+                        Analysis::check_range(&mut report, i, x as u16 + 1, name);
+                        // Some interpreters move I on, so it is not known anymore
+                        next_i = None;
+                    }
+                    _ => Analysis::report_unknown(&mut report, instruction),
+                },
+                _ => Analysis::report_unknown(&mut report, instruction),
+            }
+            if disassemble(instruction).is_none() {
+                // Nothing after an unknown opcode can be trusted
+                next.clear();
+            }
+
+            for target in targets {
+                if Analysis::check_target(&mut report, target, "Jump", rom_end) {
+                    next.push(target);
+                }
+            }
+            let successors = analysis.successors.entry(address).or_default();
+            for next in next {
+                successors.insert(next);
+                pending.push((address, (next, subroutine, next_i)));
+            }
+
+            // Code after a call is only reached once the subroutine is known to return, and I
+            // may have been changed by it
+            if let Some(called) = called {
+                let after = (address, (address + 2, subroutine, None));
+                if returns.contains(&called) {
+                    Analysis::resume(&mut analysis.successors, &mut pending, after);
+                } else {
+                    waiting.entry(called).or_default().push(after);
+                }
+            }
+            if let Some(returned) = returned {
+                if returns.insert(returned) {
+                    for after in waiting.remove(&returned).unwrap_or_default() {
+                        Analysis::resume(&mut analysis.successors, &mut pending, after);
+                    }
+                }
+            }
+        }
+
+        for subroutine in analysis.subroutines.keys() {
+            if !returns.contains(subroutine) {
+                problems.insert(Problem {
+                    address: *subroutine,
+                    severity: Severity::Warning,
+                    message: "Subroutine never returns, so calling it leaves the stack unbalanced"
+                        .to_string(),
+                });
+            }
+        }
+        analysis.problems = problems.into_iter().collect();
+        analysis
+    }
+
+    // Continues the walk after the call at `call` returned
+    fn resume(
+        successors: &mut BTreeMap<u16, BTreeSet<u16>>,
+        pending: &mut Vec<(u16, State)>,
+        (call, after): (u16, State),
+    ) {
+        successors.entry(call).or_default().insert(after.0);
+        pending.push((call, after));
+    }
+
+    fn report_unknown(report: &mut impl FnMut(Severity, String), instruction: u16) {
+        report(
+            Severity::Error,
+            format!("Unknown opcode {:04X}", instruction),
+        );
+    }
+
+    // Checks where a jump or call goes, returns whether it can be followed
+    fn check_target(
+        report: &mut impl FnMut(Severity, String),
+        target: u16,
+        kind: &str,
+        rom_end: usize,
+    ) -> bool {
+        if target < START {
+            report(
+                Severity::Error,
+                format!(
+                    "{} to {:#05X}, below the ROM in the interpreter's memory",
+                    kind, target
+                ),
+            );
+            return false;
+        }
+        if target as usize >= rom_end {
+            report(
+                Severity::Error,
+                format!("{} to {:#05X}, past the end of the ROM", kind, target),
+            );
+            return false;
+        }
+        if target % 2 != 0 {
+            report(
+                Severity::Warning,
+                format!("{} to odd address {:#05X}", kind, target),
+            );
+        }
+        true
+    }
+
+    // Checks that `len` bytes from I fit into RAM, if I is known
+    fn check_range(
+        report: &mut impl FnMut(Severity, String),
+        i: Option<u16>,
+        len: u16,
+        kind: &str,
+    ) {
+        if let Some(i) = i {
+            if i as usize + len as usize > RAM_SIZE {
+                report(
+                    Severity::Error,
+                    format!(
+                        "{} of {} bytes at I = {:#05X} runs past 0xFFF",
+                        kind, len, i
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Whether the byte at `address` belongs to a reachable instruction.
+    pub fn is_code(&self, address: u16) -> bool {
+        self.instructions.contains_key(&address)
+            || address
+                .checked_sub(1)
+                .map_or(false, |start| self.instructions.contains_key(&start))
+    }
+
+    /// Bytes of the ROM that no reachable instruction covers.
+    pub fn data_len(&self) -> usize {
+        (START..self.rom_end as u16)
+            .filter(|address| !self.is_code(*address))
+            .count()
+    }
+
+    pub fn problems_at(&self, address: u16) -> impl Iterator<Item = &Problem> {
+        self.problems
+            .iter()
+            .filter(move |problem| problem.address == address)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.problems
+            .iter()
+            .any(|problem| problem.severity == Severity::Error)
+    }
+}
+
+/// The assembly of an instruction, e.g. `LD V0, 0x12`, or `None` if it is not a known opcode.
+pub fn disassemble(instruction: u16) -> Option<String> {
+    let nnn = instruction & 0x0FFF;
+    let kk = instruction & 0x00FF;
+    let n = instruction & 0x000F;
+    let x = instruction >> 8 & 0xF;
+    let y = instruction >> 4 & 0xF;
+    let text = match instruction >> 12 {
+        0x0 => match instruction {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            _ => format!("SYS {:#05X}", nnn),
+        },
+        0x1 => format!("JP {:#05X}", nnn),
+        0x2 => format!("CALL {:#05X}", nnn),
+        0x3 => format!("SE V{:X}, {:#04X}", x, kk),
+        0x4 => format!("SNE V{:X}, {:#04X}", x, kk),
+        0x5 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+        0x6 => format!("LD V{:X}, {:#04X}", x, kk),
+        0x7 => format!("ADD V{:X}, {:#04X}", x, kk),
+        0x8 => {
+            let operation = match n {
+                0x0 => "LD",
+                0x1 => "OR",
+                0x2 => "AND",
+                0x3 => "XOR",
+                0x4 => "ADD",
+                0x5 => "SUB",
+                0x6 => "SHR",
+                0x7 => "SUBN",
+                0xE => "SHL",
+                _ => return None,
+            };
+            format!("{} V{:X}, V{:X}", operation, x, y)
+        }
+        0x9 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA => format!("LD I, {:#05X}", nnn),
+        0xB => format!("JP V0, {:#05X}", nnn),
+        0xC => format!("RND V{:X}, {:#04X}", x, kk),
+        0xD => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE if kk == 0x9E => format!("SKP V{:X}", x),
+        0xE if kk == 0xA1 => format!("SKNP V{:X}", x),
+        0xF => match kk {
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(rom: &[u8]) -> Vec<(u16, Severity, String)> {
+        Analysis::new(rom)
+            .problems
+            .into_iter()
+            .map(|problem| (problem.address, problem.severity, problem.message))
+            .collect()
+    }
+
+    #[test]
+    fn calls_continue_once_the_subroutine_returns() {
+        // CALL 0x208; CALL 0x208; JP 0x204; data; RET
+        let rom = [0x22, 0x08, 0x22, 0x08, 0x12, 0x04, 0xFF, 0xFF, 0x00, 0xEE];
+        let analysis = Analysis::new(&rom);
+        assert!(analysis.problems.is_empty());
+        assert_eq!(analysis.subroutines[&0x208], [0x200, 0x202].into());
+        assert_eq!(analysis.successors[&0x200], [0x202].into());
+        assert_eq!(analysis.successors[&0x208], BTreeSet::new());
+        assert!(!analysis.is_code(0x206));
+        assert_eq!(analysis.data_len(), 2);
+    }
+
+    #[test]
+    fn unbalanced_calls_and_returns() {
+        // CALL 0x204; JP 0x202; JP 0x204
+        let rom = [0x22, 0x04, 0x12, 0x02, 0x12, 0x04];
+        let analysis = Analysis::new(&rom);
+        assert_eq!(
+            problems(&rom),
+            vec![(
+                0x204,
+                Severity::Warning,
+                "Subroutine never returns, so calling it leaves the stack unbalanced".to_string()
+            )]
+        );
+        // The code after the call is never reached
+        assert!(!analysis.is_code(0x202));
+
+        assert_eq!(
+            problems(&[0x00, 0xEE]),
+            vec![(
+                0x200,
+                Severity::Error,
+                "Return (00EE) outside of a subroutine".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn skips_go_over_the_next_instruction() {
+        // SE V0, 0; LD V1, 1; JP 0x204
+        let analysis = Analysis::new(&[0x30, 0x00, 0x61, 0x01, 0x12, 0x04]);
+        assert!(analysis.problems.is_empty());
+        assert_eq!(analysis.successors[&0x200], [0x202, 0x204].into());
+
+        // SKP V0; JP 0x202 with nothing to skip to
+        assert_eq!(
+            problems(&[0xE0, 0x9E, 0x12, 0x02]),
+            vec![(
+                0x200,
+                Severity::Error,
+                "Execution runs past the end of the ROM".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn jumps_outside_of_the_rom() {
+        assert_eq!(
+            problems(&[0x13, 0x00]),
+            vec![(
+                0x200,
+                Severity::Error,
+                "Jump to 0x300, past the end of the ROM".to_string()
+            )]
+        );
+        assert_eq!(
+            problems(&[0x21, 0x00]),
+            vec![(
+                0x200,
+                Severity::Error,
+                "Call to 0x100, below the ROM in the interpreter's memory".to_string()
+            )]
+        );
+        assert!(Analysis::new(&[0x13, 0x00]).has_errors());
+    }
+
+    #[test]
+    fn jumps_to_odd_addresses_are_followed() {
+        // JP 0x203; data; JP 0x203
+        let analysis = Analysis::new(&[0x12, 0x03, 0x00, 0x12, 0x03]);
+        assert_eq!(analysis.instructions.get(&0x203), Some(&0x1203));
+        assert_eq!(
+            analysis.problems_at(0x200).collect::<Vec<_>>(),
+            vec![&Problem {
+                address: 0x200,
+                severity: Severity::Warning,
+                message: "Jump to odd address 0x203".to_string(),
+            }]
+        );
+        assert!(!analysis.has_errors());
+    }
+
+    #[test]
+    fn writes_past_the_end_of_memory() {
+        // LD I, 0xFFC; LD [I], V7; JP 0x204
+        assert_eq!(
+            problems(&[0xAF, 0xFC, 0xF7, 0x55, 0x12, 0x04]),
+            vec![(
+                0x202,
+                Severity::Error,
+                "Fx55 write of 8 bytes at I = 0xFFC runs past 0xFFF".to_string()
+            )]
+        );
+        // LD I, 0xFFC; LD [I], V3; LD [I], V7; JP 0x206, where I is not known after the first
+        assert!(problems(&[0xAF, 0xFC, 0xF3, 0x55, 0xF7, 0x55, 0x12, 0x06]).is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+use super::emu_thread::EmulatorThread;
+use crate::emulator::access_map::AccessMap;
+use crate::emulator::chip8::Emulator;
+use imgui::*;
+
+/// Window with a 64x64 grid of the 4 KiB of RAM, each byte colored by how it was accessed.
+pub struct AccessMapWindow {
+    pub open: bool,
+}
+
+impl AccessMapWindow {
+    const COLUMNS: usize = 64;
+    const FADE_FRAMES: u64 = 60; // frames a byte takes to fade after it was accessed
+    const UNTOUCHED: [f32; 4] = [0.12, 0.12, 0.12, 1.0];
+
+    pub fn new() -> AccessMapWindow {
+        AccessMapWindow { open: false }
+    }
+
+    // Each kind of access adds a color channel: executed is green, read is blue and written is
+    // red, so e.g. self-modifying code shows up in yellow. Bytes dim once they are not accessed.
+    fn color(kinds: u8, age: u64) -> [f32; 4] {
+        let fade = 1.0
+            - age.min(AccessMapWindow::FADE_FRAMES) as f32 / AccessMapWindow::FADE_FRAMES as f32;
+        let brightness = 0.35 + 0.65 * fade;
+        let channel = |kind: u8| {
+            if kinds & kind != 0 {
+                brightness
+            } else {
+                0.0
+            }
+        };
+        [
+            channel(AccessMap::WRITE),
+            channel(AccessMap::EXECUTE),
+            channel(AccessMap::READ),
+            1.0,
+        ]
+    }
+
+    pub fn render(&mut self, ui: &Ui, emulator: &EmulatorThread) {
+        if !self.open {
+            return;
+        }
+        // Only locked while the window is open
+        let mut emulator = emulator.lock();
+        let emulator: &mut Emulator = &mut emulator;
+
+        let mut open = self.open;
+        imgui::Window::new(im_str!("Memory Access"))
+            .size([340.0, 420.0], Condition::FirstUseEver)
+            .position([440.0, 80.0], Condition::FirstUseEver)
+            .opened(&mut open)
+            .build(ui, || {
+                let mut enabled = emulator.access_map.is_some();
+                if ui.checkbox(im_str!("Track accesses"), &mut enabled) {
+                    emulator.access_map = if enabled {
+                        Some(Box::new(AccessMap::new(emulator.ram.len())))
+                    } else {
+                        None
+                    };
+                }
+                let access_map = match &mut emulator.access_map {
+                    Some(access_map) => access_map,
+                    None => {
+                        ui.text_disabled("Switched off, so it does not slow the emulator down.");
+                        return;
+                    }
+                };
+                ui.same_line(0.0);
+                if ui.small_button(im_str!("Clear")) {
+                    access_map.clear();
+                }
+                for (label, kinds) in [
+                    ("Executed", AccessMap::EXECUTE),
+                    ("Read", AccessMap::READ),
+                    ("Written", AccessMap::WRITE),
+                    ("Self-modifying", AccessMap::EXECUTE | AccessMap::WRITE),
+                ]
+                .iter()
+                {
+                    ui.text_colored(AccessMapWindow::color(*kinds, 0), label);
+                    ui.same_line(0.0);
+                }
+                ui.new_line();
+
+                AccessMapWindow::render_grid(ui, access_map, &emulator.ram);
+            });
+        self.open = open;
+    }
+
+    fn render_grid(ui: &Ui, access_map: &AccessMap, ram: &[u8]) {
+        let rows = (ram.len() + AccessMapWindow::COLUMNS - 1) / AccessMapWindow::COLUMNS;
+        let cell = (ui.content_region_avail()[0] / AccessMapWindow::COLUMNS as f32)
+            .floor()
+            .max(2.0);
+        let [left, top] = ui.cursor_screen_pos();
+
+        let draw_list = ui.get_window_draw_list();
+        for address in 0..ram.len() {
+            let color = match access_map.age(address) {
+                Some(age) => AccessMapWindow::color(access_map.kinds(address), age),
+                None => AccessMapWindow::UNTOUCHED,
+            };
+            let x = left + (address % AccessMapWindow::COLUMNS) as f32 * cell;
+            let y = top + (address / AccessMapWindow::COLUMNS) as f32 * cell;
+            // Leave a gap between the cells when they are large enough
+            let size = if cell >= 4.0 { cell - 1.0 } else { cell };
+            draw_list
+                .add_rect([x, y], [x + size, y + size], color)
+                .filled(true)
+                .build();
+        }
+        drop(draw_list);
+
+        // Reserves the space of the grid and tells which byte the mouse is over
+        let size = [AccessMapWindow::COLUMNS as f32 * cell, rows as f32 * cell];
+        ui.invisible_button(im_str!("##grid"), size);
+        if ui.is_item_hovered() {
+            let [mouse_x, mouse_y] = ui.io().mouse_pos;
+            let column = (((mouse_x - left) / cell) as usize).min(AccessMapWindow::COLUMNS - 1);
+            let row = ((mouse_y - top) / cell) as usize;
+            let address = row * AccessMapWindow::COLUMNS + column;
+            if address < ram.len() {
+                let kinds = access_map.kinds(address);
+                let names: Vec<&str> = [
+                    (AccessMap::EXECUTE, "executed"),
+                    (AccessMap::READ, "read"),
+                    (AccessMap::WRITE, "written"),
+                ]
+                .iter()
+                .filter(|(kind, _)| kinds & kind != 0)
+                .map(|(_, name)| *name)
+                .collect();
+                ui.tooltip_text(format!(
+                    "{:03X}: {:02X}\n{}",
+                    address,
+                    ram[address],
+                    if names.is_empty() {
+                        "untouched".to_string()
+                    } else {
+                        names.join(", ")
+                    }
+                ));
+            }
+        }
+    }
+}
@@ -1,118 +1,569 @@
+use super::access_map_window::AccessMapWindow;
+use super::cheat_window::CheatWindow;
+use super::emu_thread::{CpuState, EmulatorThread, Input};
 use super::emu_window::{self, EmulatorWindow};
-use crate::emulator::{chip8, keyboard::Keyboard};
+use super::file_browser::FileBrowser;
+use super::layout::Layout;
+use super::profiler_window::ProfilerWindow;
+use super::rom_browser::RomBrowser;
+use super::smc_window::SmcWindow;
+use crate::analysis::{self, Analysis, Severity};
+use crate::cli::Options;
+use crate::config::settings::Settings;
+use crate::config::Config;
+use crate::emulator::keyboard::{self, KeyLayout};
+use crate::emulator::known::RomSettings;
+use crate::emulator::screen::Screen;
+use crate::emulator::{chip8, quirks::QuirkPreset, quirks::Quirks};
 use crate::imgui_wgpu_backend::{Renderer, RendererConfig};
+use crate::library::history::History;
+use crate::library::rom::RomInfo;
+use crate::library::RomLibrary;
+use crate::server::{RomRequest, Server};
 use emu_window::RGBA;
 use futures::executor::block_on;
-use glob::glob;
 use imgui::*;
 use imgui_winit_support;
-use std::path::PathBuf;
+use std::fs;
+use std::io;
+use std::path::Path;
 use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver};
 use std::time::Instant;
 use wgpu::Instance;
 use winit::{
     dpi::{LogicalPosition, LogicalSize},
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::Window,
+    window::{Fullscreen, Window},
 };
 
 pub struct Application {
-    emulator: chip8::Emulator,
-    roms: Vec<PathBuf>,
+    emulator: EmulatorThread,
+    library: RomLibrary,
+    current_rom: Option<RomInfo>,
+    analysis: Option<Analysis>, // of the current ROM, tells code from data in the Code window
+    history: History,
+    browser: RomBrowser,
+    file_browser: FileBrowser,
+    cheats: CheatWindow,
+    profiler: ProfilerWindow,
+    access_map: AccessMapWindow,
+    smc: SmcWindow,
+    error: Option<String>, // last ROM that failed to load, shown in the ROM list
+    rom_requests: Option<Receiver<RomRequest>>, // ROMs to load for the server's clients
+    settings: Settings,
+    overrides: RomSettings, // settings from the command line, used over a ROM's recommendations
+    scale: Option<f32>,     // scale from the command line, not saved to the settings
+    game_mode: bool,        // fullscreen with only the display shown
+    reset_layout: bool,     // move the windows back to their default places next frame
 }
 
 impl Application {
-    pub fn new() -> Application {
-        Application {
-            emulator: chip8::Emulator::new(),
-            roms: Application::load_roms(),
+    pub fn new(options: Options) -> Application {
+        let mut app = Application {
+            emulator: EmulatorThread::spawn(chip8::Emulator::new()),
+            library: RomLibrary::new(RomLibrary::search_paths(&options.rom_dirs, &Config::load())),
+            current_rom: None,
+            analysis: None,
+            history: History::load(),
+            browser: RomBrowser::new(),
+            file_browser: FileBrowser::new(),
+            cheats: CheatWindow::new(),
+            profiler: ProfilerWindow::new(),
+            access_map: AccessMapWindow::new(),
+            smc: SmcWindow::new(),
+            error: None,
+            rom_requests: None,
+            settings: Settings::load(),
+            overrides: options.settings,
+            scale: options.scale,
+            game_mode: options.fullscreen,
+            reset_layout: false,
+        };
+
+        app.emulator.lock().set_seed(options.seed);
+        if let Some(port) = options.server {
+            let (sender, receiver) = mpsc::channel();
+            match Server::start(port, app.emulator.shared(), sender) {
+                Ok(()) => app.rom_requests = Some(receiver),
+                Err(e) => eprintln!(
+                    "Failed to start server on port: '{0}', [ERROR]: {1}",
+                    port, e
+                ),
+            }
         }
+        if let Some(rom) = options.rom {
+            app.start_rom(&rom, options.paused);
+        } else if let Some(rom) = app.settings.last_rom.clone() {
+            // Pick up where the last session left off, without starting the game right away
+            if rom.is_file() {
+                app.start_rom(&rom, true);
+            }
+        }
+
+        app
     }
 
-    pub fn render(&mut self, ui: &imgui::Ui) {
+    // Returns false if the ROM could not be loaded, the reason is left in `error`. A `paused`
+    // ROM does not run any instruction until it is resumed.
+    fn start_rom(&mut self, path: &Path, paused: bool) -> bool {
+        let path = &fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let rom = match self.library.find(path) {
+            Some(rom) => rom.clone(),
+            None => match RomInfo::from_path(path) {
+                Ok(rom) => rom,
+                Err(e) => {
+                    self.report_error(path, e);
+                    return false;
+                }
+            },
+        };
+        let settings = self.overrides.or(rom.recommended.unwrap_or_default());
+
+        // A ROM that fails to load leaves the running one and its settings alone
+        let contents = match fs::read(&rom.path).and_then(|contents| {
+            chip8::Emulator::check_rom(&contents)?;
+            Ok(contents)
+        }) {
+            Ok(contents) => contents,
+            Err(e) => {
+                self.report_error(&rom.path, e);
+                return false;
+            }
+        };
+
+        // Analyzed before taking the lock, and used for the platform too
+        let analysis = Analysis::new(&contents);
+        let mut emulator = self.emulator.lock();
+        self.settings.configure(&mut emulator, settings);
+        if let Err(e) = emulator.load_analyzed_rom(&contents, &analysis) {
+            drop(emulator);
+            self.report_error(&rom.path, e);
+            return false;
+        }
+        emulator.pause = paused;
+        self.cheats.set_rom(&rom.hash, &mut emulator);
+        drop(emulator);
+        self.analysis = Some(analysis);
+        self.history.add_recent(&rom.path);
+        self.settings.last_rom = Some(rom.path.clone());
+        self.current_rom = Some(rom);
+        self.error = None;
+        true
+    }
+
+    fn report_error(&mut self, path: &Path, error: io::Error) {
+        eprintln!(
+            "Failed to load ROM: '{0}', [ERROR]: {1}",
+            path.display(),
+            error
+        );
+        self.error = Some(format!("Failed to load '{}': {}", path.display(), error));
+    }
+
+    pub fn render(&mut self, ui: &imgui::Ui, layout: &Layout) {
+        // Loaded like any other ROM, so the cheats, history and analysis follow it
+        let requests: Vec<RomRequest> = self
+            .rom_requests
+            .iter()
+            .flat_map(Receiver::try_iter)
+            .collect();
+        for request in requests {
+            // The client may have given up waiting, and then the ROM is not loaded
+            let reply = match request.accept() {
+                Some(reply) => reply,
+                None => continue,
+            };
+            let result = if self.start_rom(&request.path, request.paused) {
+                Ok(())
+            } else {
+                Err(self.error.clone().unwrap_or_default())
+            };
+            // The client may have disconnected
+            let _ = reply.send(result);
+        }
+
+        if self.game_mode {
+            return;
+        }
+
         // Window with list of ROMs
         let win = imgui::Window::new(im_str!("ROMs Available"));
-        win.size([363.0f32, 623.0f32], Condition::Once)
-            .position([1031.0f32, 5.0f32], Condition::Once)
-            .resizable(false)
-            .build(&ui, || {
-                for rom in &self.roms {
-                    let filename = ImString::new(rom.file_name().unwrap().to_str().unwrap());
-                    if ui.button(&filename, [333.0f32, 0f32]) {
-                        self.emulator.load_rom(rom);
+        layout.place(win, &layout.roms).build(&ui, || {
+            if ui.button(im_str!("Open..."), [0.0, 0.0]) {
+                let dir = self
+                    .current_rom
+                    .as_ref()
+                    .and_then(|rom| rom.path.parent().map(Path::to_path_buf));
+                self.file_browser.show(dir.as_deref());
+            }
+            ui.same_line(0.0);
+            if ui.button(im_str!("Rescan"), [0.0, 0.0]) {
+                self.library.rescan();
+            }
+            if ui.is_item_hovered() {
+                let dirs: Vec<String> = self
+                    .library
+                    .dirs()
+                    .iter()
+                    .map(|dir| dir.display().to_string())
+                    .collect();
+                ui.tooltip_text(format!("Searching for ROMs in:\n{}", dirs.join("\n")));
+            }
+            ui.same_line(0.0);
+            ui.text_disabled(format!("{} ROMs", self.library.roms().len()));
+
+            let mut dismissed = false;
+            if let Some(error) = &self.error {
+                ui.text_colored(RGBA::to_rgba_normalized([255, 100, 100, 255]), error);
+                dismissed = ui.small_button(im_str!("Dismiss"));
+            }
+            if dismissed {
+                self.error = None;
+            }
+
+            if let Some(crash) = self.emulator.crash().map(str::to_string) {
+                ui.text_colored(
+                    RGBA::to_rgba_normalized([255, 100, 100, 255]),
+                    format!("The emulator stopped: {}", crash),
+                );
+                if ui.small_button(im_str!("Restart")) {
+                    self.emulator.restart();
+                }
+            }
+
+            let errors = self.library.errors();
+            if !errors.is_empty() {
+                let color = ui.push_style_color(
+                    StyleColor::Text,
+                    RGBA::to_rgba_normalized([255, 180, 0, 255]),
+                );
+                let header = ImString::new(format!("{} problems while scanning", errors.len()));
+                let open = CollapsingHeader::new(&header).build(ui);
+                color.pop(ui);
+                if open {
+                    for error in errors {
+                        ui.text_wrapped(&ImString::new(error));
                     }
                 }
-            });
+            }
+
+            if let Some(path) = self
+                .browser
+                .render(ui, self.library.roms(), &mut self.history)
+            {
+                self.start_rom(&path, false);
+            }
+        });
+
+        if let Some(path) = self.file_browser.render(ui) {
+            self.start_rom(&path, false);
+        }
+        self.cheats.render(ui, &self.emulator);
+        let name = self.capture_name();
+        self.profiler.render(ui, &self.emulator, &name);
+        self.access_map.render(ui, &self.emulator);
+        self.smc.render(ui, &self.emulator, &mut self.settings);
+
+        // The windows below show a copy, so the emulator keeps running while they are built
+        let state = self.emulator.cpu_state();
 
         // Window with current CPU State
         let window = imgui::Window::new(im_str!("Current CPU State"));
-        window
-            .size([300.0f32, 210.0f32], Condition::FirstUseEver)
-            .position([728.0f32, 418.0f32], Condition::Once)
-            .resizable(false)
-            .build(&ui, || {
-                ui.text(format!("PC: {:#X}", self.emulator.pc));
-                ui.text(format!("I: {:#X}", self.emulator.i));
-                for i in 0..self.emulator.v.len() {
-                    ui.text(format!("V{:X}: {:#X} ", i, self.emulator.v[i]));
-                    if (i + 1) % 4 != 0 {
-                        ui.same_line(0.0f32);
-                    }
-                }
-                ui.text(format!("Delay Timer: {}", self.emulator.delay_timer));
-                ui.text(format!("Sound Timer: {}", self.emulator.sound_timer));
-
-                ui.text(format!(
-                    "Stack:\n(Size: {}),\nValues:",
-                    self.emulator.stack.len()
-                ));
-                for v in self.emulator.stack.iter() {
-                    ui.text(format!("{:X}", v));
-                    ui.same_line(0.0);
+        layout.place(window, &layout.cpu).build(&ui, || {
+            if ui.small_button(im_str!("Cheats...")) {
+                self.cheats.open = true;
+            }
+            ui.same_line(0.0);
+            if ui.small_button(im_str!("Profiler...")) {
+                self.profiler.open = true;
+            }
+            ui.same_line(0.0);
+            if ui.small_button(im_str!("Memory...")) {
+                self.access_map.open = true;
+            }
+            ui.same_line(0.0);
+            // The count is left out of the ID, so the button stays the same widget
+            let label = ImString::new(format!("SMC ({})...###smc", state.smc_writes));
+            if ui.small_button(&label) {
+                self.smc.open = true;
+            }
+            ui.text(format!("PC: {:#X}", state.pc));
+            ui.text(format!("I: {:#X}", state.i));
+            for i in 0..state.v.len() {
+                ui.text(format!("V{:X}: {:#X} ", i, state.v[i]));
+                if (i + 1) % 4 != 0 {
+                    ui.same_line(0.0f32);
                 }
-            });
+            }
+            ui.text(format!("Delay Timer: {}", state.delay_timer));
+            ui.text(format!("Sound Timer: {}", state.sound_timer));
+
+            ui.text(format!("Stack:\n(Size: {}),\nValues:", state.stack.len()));
+            for v in state.stack.iter() {
+                ui.text(format!("{:X}", v));
+                ui.same_line(0.0);
+            }
+        });
 
         // Window with program code
         let window = imgui::Window::new(im_str!("Code"));
-        window
-            .size([300.0, 410.0], Condition::FirstUseEver)
-            .position([728.0, 5.0], Condition::Once)
-            .resizable(false)
-            .build(&ui, || {
-                let code_location = self.emulator.code_memory_location();
-                let pc = self.emulator.pc as usize;
-                let code = &self.emulator.ram[code_location.0..code_location.1];
-                for i in (1..code.len()).step_by(2) {
-                    let mut color_stack: Option<ColorStackToken> = None;
-                    if pc == (i + code_location.0 - 1) {
-                        ui.set_scroll_here_y();
-                        color_stack = Some(ui.push_style_color(
-                            StyleColor::Text,
-                            RGBA::to_rgba_normalized([0, 255, 0, 255]),
-                        ));
-                    }
-                    ui.text(format!("{:>4}: {:02X}{:02X}", i, code[i - 1], code[i]));
-                    if let Some(c) = color_stack {
-                        c.pop(&ui);
+        layout.place(window, &layout.code).build(&ui, || {
+            let code_location = state.code;
+            let pc = state.pc as usize;
+            let ram = &state.ram;
+            // Color the code by how often it ran while the profiler is on
+            let max_hits = state
+                .hits
+                .as_ref()
+                .map(|hits| hits.iter().copied().max().unwrap_or(0));
+            // Without an analysis everything is shown as code, as if it were all instructions
+            let is_code = |address: usize| {
+                address == pc
+                    || self.analysis.as_ref().map_or(true, |analysis| {
+                        analysis.instructions.contains_key(&(address as u16))
+                    })
+            };
+            let mut address = code_location.0;
+            while address < code_location.1 {
+                let start = address;
+                let text = if is_code(address) && address + 1 < code_location.1 {
+                    let instruction = (ram[address] as u16) << 8 | ram[address + 1] as u16;
+                    address += 2;
+                    format!(
+                        "{:03X}: {:04X}  {}",
+                        start,
+                        instruction,
+                        analysis::disassemble(instruction).unwrap_or_else(|| "???".to_string())
+                    )
+                } else {
+                    // Up to 4 bytes of data on a line
+                    while address < code_location.1
+                        && address - start < 4
+                        && (address == start || !is_code(address))
+                    {
+                        address += 1;
                     }
+                    let bytes: Vec<String> = ram[start..address]
+                        .iter()
+                        .map(|byte| format!("{:#04X}", byte))
+                        .collect();
+                    format!("{:03X}: db {}", start, bytes.join(", "))
+                };
+
+                let problems: Vec<&analysis::Problem> = self
+                    .analysis
+                    .iter()
+                    .flat_map(|analysis| analysis.problems_at(start as u16))
+                    .collect();
+                let mut color_stack: Option<ColorStackToken> = None;
+                if pc == start {
+                    ui.set_scroll_here_y();
+                    color_stack = Some(ui.push_style_color(
+                        StyleColor::Text,
+                        RGBA::to_rgba_normalized([0, 255, 0, 255]),
+                    ));
+                } else if let Some(problem) = problems.iter().max_by_key(|problem| problem.severity)
+                {
+                    let color = match problem.severity {
+                        Severity::Error => [255, 80, 80, 255],
+                        Severity::Warning => [255, 180, 0, 255],
+                    };
+                    color_stack = Some(
+                        ui.push_style_color(StyleColor::Text, RGBA::to_rgba_normalized(color)),
+                    );
+                } else if let (Some(hits), Some(max_hits)) = (&state.hits, max_hits) {
+                    let hits = hits[start];
+                    color_stack = Some(ui.push_style_color(
+                        StyleColor::Text,
+                        ProfilerWindow::heat_color(hits, max_hits),
+                    ));
                 }
-            });
+                ui.text(text);
+                if let Some(c) = color_stack {
+                    c.pop(&ui);
+                }
+                if !problems.is_empty() && ui.is_item_hovered() {
+                    let messages: Vec<String> = problems
+                        .iter()
+                        .map(|problem| format!("{}: {}", problem.severity, problem.message))
+                        .collect();
+                    ui.tooltip_text(messages.join("\n"));
+                }
+            }
+        });
+
+        self.render_settings(ui, layout);
 
         // Help Window
         let window = imgui::Window::new(im_str!("About"));
-        window
-            .size([720.0, 210.0], Condition::FirstUseEver)
-            .position([5.0, 418.0], Condition::Once)
-            .resizable(false)
+        layout.place(window, &layout.about).build(&ui, || match &self.current_rom {
+                Some(rom) => {
+                    let settings = self.overrides.or(rom.recommended.unwrap_or_default());
+                    Application::render_rom_info(ui, rom, settings, &state)
+                }
+                None => {
+                    ui.text(im_str!("Welcome to CHIPPUS! Yet another Chip8 Emulator written by a noob learning Rust!"));
+                    ui.text(im_str!("\nHow to use this Emulator?"));
+                    ui.text(im_str!("Step - 1:\n\tSelect ROM file."));
+                    ui.text(im_str!("\nStep - 2:\n\tUse these Controls:\n\t1,2,3,4,\n\tQ,W,E,R,\n\tA,S,D,F,\n\tZ,X,C,V"));
+                }
+            });
+    }
+
+    // Defaults for when neither the command line nor the ROM's recommendations say otherwise.
+    // Changes also apply to the running ROM right away.
+    fn render_settings(&mut self, ui: &imgui::Ui, layout: &Layout) {
+        let window = imgui::Window::new(im_str!("Settings"));
+        layout
+            .place(window, &layout.settings)
+            .collapsed(true, layout.condition)
             .build(&ui, || {
-                ui.text(im_str!("Welcome to CHIPPUS! Yet another Chip8 Emulator written by a noob learning Rust!"));
-                ui.text(im_str!("\nHow to use this Emulator?"));
-                ui.text(im_str!("Step - 1:\n\tSelect ROM file."));
-                ui.text(im_str!("\nStep - 2:\n\tUse these Controls:\n\t1,2,3,4,\n\tQ,W,E,R,\n\tA,S,D,F,\n\tZ,X,C,V"));
+                if Slider::new(im_str!("Clock (Hz)"))
+                    .range(100..=2000)
+                    .build(ui, &mut self.settings.clock_speed)
+                {
+                    self.emulator.lock().clock_speed = self.settings.clock_speed;
+                }
+
+                let mut preset = QuirkPreset::ALL
+                    .iter()
+                    .position(|preset| *preset == self.settings.quirks)
+                    .unwrap_or(0);
+                if ComboBox::new(im_str!("Quirks")).build_simple(
+                    ui,
+                    &mut preset,
+                    &QuirkPreset::ALL,
+                    &|preset| ImString::new(preset.label()).into(),
+                ) {
+                    self.settings.quirks = QuirkPreset::ALL[preset];
+                    self.emulator.lock().quirks = Quirks::from_preset(self.settings.quirks);
+                }
+                ui.checkbox(im_str!("Detect quirks"), &mut self.settings.detect_quirks);
+                if ui.is_item_hovered() {
+                    ui.tooltip_text(
+                        "Use the quirks of the platform a ROM seems to be written for when it is loaded",
+                    );
+                }
+
+                let bindings = self.settings.bindings();
+                let current = KeyLayout::ALL
+                    .iter()
+                    .find(|layout| layout.bindings() == bindings)
+                    .map_or("custom", |layout| layout.name());
+                let preview = ImString::new(current);
+                ComboBox::new(im_str!("Keys"))
+                    .preview_value(&preview)
+                    .build(ui, || {
+                        for layout in KeyLayout::ALL.iter() {
+                            let label = ImString::new(layout.name());
+                            if Selectable::new(&label)
+                                .selected(layout.name() == current)
+                                .build(ui)
+                            {
+                                self.settings.keys = layout.bindings().iter().collect();
+                                self.emulator
+                                    .lock()
+                                    .keyboard
+                                    .set_bindings(layout.bindings());
+                            }
+                        }
+                    });
+                if ui.is_item_hovered() {
+                    ui.tooltip_text(format!("Keys 0-F: {}", bindings.iter().collect::<String>()));
+                }
+
+                if ui.button(im_str!("Reset layout"), [0.0, 0.0]) {
+                    self.reset_layout = true;
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text(
+                        "Move the windows back to their places for the current window size",
+                    );
+                }
             });
     }
 
+    // `settings` are the ones the ROM was started with
+    fn render_rom_info(ui: &imgui::Ui, rom: &RomInfo, settings: RomSettings, state: &CpuState) {
+        ui.text(&rom.title);
+        if let Some(variant) = &rom.variant {
+            ui.same_line(0.0);
+            ui.text_disabled(format!("({})", variant));
+        }
+        ui.text(format!(
+            "Author: {}    Year: {}",
+            rom.author.as_deref().unwrap_or("unknown"),
+            rom.year.as_deref().unwrap_or("unknown")
+        ));
+        ui.text_disabled(format!("SHA-1: {}", rom.hash));
+        if let Some(settings) = rom.recommended {
+            ui.text(format!(
+                "Recommended: quirks {}, clock {}, keys {}",
+                settings.quirks.map_or("-", |preset| preset.label()),
+                settings
+                    .clock_speed
+                    .map_or("-".to_string(), |speed| format!("{} Hz", speed)),
+                settings.key_layout.map_or("-", |layout| layout.name())
+            ));
+        }
+        if let Some(detection) = &state.detection {
+            // The detected quirks are only used when none were given for the ROM
+            let quirks = match settings.quirks {
+                Some(preset) => Some(preset),
+                None if state.detect_quirks => detection.preset,
+                None => None,
+            };
+            ui.text(format!(
+                "Platform: {} ({:.0}% sure), quirks {}",
+                detection.platform,
+                detection.confidence * 100.0,
+                quirks.map_or("from settings", |preset| preset.label())
+            ));
+            if ui.is_item_hovered() && !detection.reasons.is_empty() {
+                ui.tooltip_text(detection.reasons.join("\n"));
+            }
+        }
+
+        // CHIP-8 keys in keypad order with the keyboard key bound to each of them
+        let bindings = state.bindings;
+        let keypad = [
+            [0x1, 0x2, 0x3, 0xC],
+            [0x4, 0x5, 0x6, 0xD],
+            [0x7, 0x8, 0x9, 0xE],
+            [0xA, 0x0, 0xB, 0xF],
+        ];
+        ui.separator();
+        ui.group(|| {
+            ui.text("Keypad:");
+            for row in keypad.iter() {
+                let keys: Vec<String> = row
+                    .iter()
+                    .map(|key| format!("{:X}={}", key, bindings[*key]))
+                    .collect();
+                ui.text(keys.join(" "));
+            }
+        });
+        ui.same_line(0.0);
+        ui.group(|| {
+            let controls = rom.controls();
+            if controls.is_empty() {
+                ui.text_disabled("No controls described for this ROM.");
+            } else {
+                ui.text("Controls:");
+                for sentence in controls {
+                    ui.text_wrapped(&ImString::new(format!("- {}.", sentence)));
+                }
+            }
+        });
+
+        if let Some(description) = &rom.description {
+            ui.separator();
+            ui.text_wrapped(&ImString::new(description));
+        }
+    }
+
     fn style_ui(&self, imgui: &mut Context) {
         let style = imgui.style_mut();
         style.window_rounding = 8.0;
@@ -143,40 +594,64 @@ impl Application {
         style[imgui::StyleColor::MenuBarBg] = RGBA::to_rgba_normalized([216, 179, 132, 30]);
     }
 
-    fn load_roms() -> Vec<PathBuf> {
-        let executable_path = std::env::current_exe();
-        let rom_path = executable_path
-            .unwrap()
-            .parent()
-            .unwrap()
-            .join("../../roms");
-
-        glob(rom_path.join("**/*.ch8").to_str().unwrap())
-            .unwrap()
-            .map(|r| r.unwrap())
-            .collect()
+    fn set_game_mode(&mut self, window: &Window, game_mode: bool) {
+        self.game_mode = game_mode;
+        window.set_fullscreen(if game_mode {
+            Some(Fullscreen::Borderless(None))
+        } else {
+            None
+        });
+    }
+
+    // Screenshots and recordings are named after the running ROM
+    fn capture_name(&self) -> String {
+        self.current_rom
+            .as_ref()
+            .map_or_else(|| "chippus".to_string(), |rom| rom.title.clone())
     }
 
     fn set_key_state(&mut self, code: VirtualKeyCode, state: bool) {
-        self.emulator.keyboard.set(Keyboard::map_key(code), state)
+        // The key codes are named after the characters, e.g. `Key1` and `Q`
+        if let Some(c) = keyboard::key_char(&format!("{:?}", code)) {
+            self.emulator.send(Input::Key(c, state));
+        }
     }
 
-    pub fn run(mut self: Rc<Self>) {
+    /// Checks whether wgpu can find a graphics adapter to render with.
+    pub fn has_adapter() -> bool {
+        let instance = Instance::new(wgpu::BackendBit::PRIMARY);
+        block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+        }))
+        .is_some()
+    }
+
+    /// Opens the window and runs until it is closed. Only returns if the window cannot be
+    /// created or wgpu cannot render to it, which happens before the UI starts.
+    pub fn run(mut self: Rc<Self>) -> io::Result<()> {
         // Set up window and GPU
         let event_loop = EventLoop::new();
 
         let instance = Instance::new(wgpu::BackendBit::PRIMARY);
 
         let (window, size, surface) = {
-            let window = Window::new(&event_loop).unwrap();
-            window.set_resizable(false);
-            window.set_inner_size(LogicalSize {
-                width: 1398.0,
-                height: 632.0,
-            });
+            let window = Window::new(&event_loop)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+            let [width, height] = match self.scale {
+                Some(scale) => {
+                    Layout::window_size(scale, [Screen::WIDTH as f32, Screen::HEIGHT as f32])
+                }
+                None => self.settings.window_size,
+            };
+            window.set_resizable(true);
+            window.set_inner_size(LogicalSize { width, height });
             window.set_title("CHIPPUS - CHIP8 EMU");
             //TODO: Should be dynamic
             window.set_outer_position(LogicalPosition { x: 20.0, y: 100.0 });
+            if self.game_mode {
+                window.set_fullscreen(Some(Fullscreen::Borderless(None)));
+            }
             let size = window.inner_size();
 
             let surface = unsafe { instance.create_surface(&window) };
@@ -186,14 +661,22 @@ impl Application {
 
         let hidpi_factor = 1.0;
 
+        // An adapter can be found without being able to draw to the window, e.g. on some
+        // remote desktops
         let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
             power_preference: wgpu::PowerPreference::HighPerformance,
             compatible_surface: Some(&surface),
         }))
-        .unwrap();
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no graphics adapter can draw to the window",
+            )
+        })?;
 
         let (device, mut queue) =
-            block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).unwrap();
+            block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None))
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
 
         // Set up swap chain
         let sc_desc = wgpu::SwapChainDescriptor {
@@ -214,7 +697,9 @@ impl Application {
             &window,
             imgui_winit_support::HiDpiMode::Default,
         );
+        // The layout is kept in the settings file instead of imgui.ini
         imgui.set_ini_filename(None);
+        imgui.load_ini_settings(&self.settings.layout);
 
         let font_size = (13.0 * hidpi_factor) as f32;
         imgui.io_mut().font_global_scale = (1.0 / hidpi_factor) as f32;
@@ -247,7 +732,7 @@ impl Application {
 
         let mut last_frame = Instant::now();
 
-        let mut screen = EmulatorWindow::new(&mut renderer, &device);
+        let mut screen = EmulatorWindow::new(&mut renderer, &device, &self.settings);
 
         let mut last_cursor = None;
 
@@ -277,6 +762,21 @@ impl Application {
 
                     swap_chain = device.create_swap_chain(&surface, &sc_desc);
                 }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::Escape),
+                                    state: ElementState::Pressed,
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } if self_mut.game_mode => {
+                    self_mut.set_game_mode(&window, false);
+                }
                 Event::WindowEvent {
                     event:
                         WindowEvent::KeyboardInput {
@@ -296,6 +796,75 @@ impl Application {
                 } => {
                     *control_flow = ControlFlow::Exit;
                 }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::F11),
+                                    state: ElementState::Pressed,
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    let game_mode = !self_mut.game_mode;
+                    self_mut.set_game_mode(&window, game_mode);
+                }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::F12),
+                                    state: ElementState::Pressed,
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    screen.save_screenshot(&self_mut.capture_name());
+                }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::F9),
+                                    state: ElementState::Pressed,
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    screen.toggle_recording(&self_mut.capture_name());
+                }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::Tab),
+                                    state,
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    self_mut
+                        .emulator
+                        .send(Input::Turbo(state == ElementState::Pressed));
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::DroppedFile(ref path),
+                    ..
+                } => {
+                    self_mut.start_rom(path, false);
+                }
                 Event::WindowEvent {
                     event:
                         WindowEvent::KeyboardInput {
@@ -311,6 +880,21 @@ impl Application {
                 } => {
                     self_mut.set_key_state(virtual_keycode, state == ElementState::Pressed);
                 }
+                Event::LoopDestroyed => {
+                    self_mut.emulator.stop();
+                    screen.stop_recording();
+
+                    let settings = &mut self_mut.settings;
+                    screen.save_settings(settings);
+                    // Keep the size the user gave the window, not the one picked for them
+                    if !self_mut.game_mode && self_mut.scale.is_none() {
+                        let size = window.inner_size().to_logical::<f32>(window.scale_factor());
+                        settings.window_size = [size.width, size.height];
+                    }
+                    settings.layout.clear();
+                    imgui.save_ini_settings(&mut settings.layout);
+                    settings.save();
+                }
                 Event::MainEventsCleared => {
                     window.request_redraw();
                 }
@@ -332,20 +916,25 @@ impl Application {
                         .expect("Failed to prepare frame");
                     let ui = imgui.frame();
 
-                    // Run emulator update
-                    self_mut.emulator.execute_cycle(ui.io().delta_time);
+                    // Update the screen buffer with the newest frame from the emulator thread
+                    screen.update(
+                        self_mut.emulator.take_frame(),
+                        &mut renderer,
+                        &device,
+                        &mut queue,
+                        ui.io().delta_time,
+                    );
 
-                    // Read and update screen buffer if changed:
-                    if self_mut.emulator.screen.is_dirty() {
-                        self_mut.emulator.screen.set_dirty(false);
-
-                        screen.update(&self_mut.emulator, &mut renderer, &device, &mut queue);
-                    }
+                    let layout = Layout::new(ui.io().display_size, self_mut.reset_layout);
+                    self_mut.reset_layout = false;
 
                     // Draw actual app UI
-                    self_mut.render(&ui);
+                    self_mut.render(&ui, &layout);
                     // Draw screen window
-                    screen.render(&ui, &mut self_mut.emulator, ui.io().delta_time);
+                    if screen.render(&ui, &self_mut.emulator, &layout, self_mut.game_mode) {
+                        let game_mode = !self_mut.game_mode;
+                        self_mut.set_game_mode(&window, game_mode);
+                    }
 
                     let mut encoder: wgpu::CommandEncoder = device
                         .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
@@ -0,0 +1,226 @@
+use crate::config;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::imageops::{self, FilterType};
+use image::{Delay, Frame, ImageResult, RgbaImage};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Returns a new file path for a capture of the given ROM, in the pictures directory if there
+/// is one.
+pub fn capture_path(name: &str, extension: &str) -> PathBuf {
+    let dir = dirs::picture_dir()
+        .map(|dir| dir.join("Chippus"))
+        .or_else(config::data_dir)
+        .unwrap_or_default();
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or(0);
+    dir.join(format!("{}-{}.{}", name, time, extension))
+}
+
+// Turns the RGBA pixels of the display into an image, with every pixel `scale` pixels wide
+fn scaled_image(data: &[u8], width: u32, height: u32, scale: u32) -> RgbaImage {
+    let image = RgbaImage::from_raw(width, height, data.to_vec())
+        .expect("Display data does not match its size");
+    let scale = scale.max(1);
+    imageops::resize(&image, width * scale, height * scale, FilterType::Nearest)
+}
+
+fn create_parent_dir(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(dir) => fs::create_dir_all(dir),
+        None => Ok(()),
+    }
+}
+
+/// Saves the display as a PNG file.
+pub fn save_screenshot(data: &[u8], width: u32, height: u32, scale: u32, path: &Path) {
+    let result = create_parent_dir(path)
+        .map_err(|e| e.to_string())
+        .and_then(|_| {
+            scaled_image(data, width, height, scale)
+                .save(path)
+                .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(()) => println!("Saved screenshot to '{}'", path.display()),
+        Err(e) => eprintln!(
+            "Failed to save screenshot: '{0}', [ERROR]: {1}",
+            path.display(),
+            e
+        ),
+    }
+}
+
+/// Records the displayed frames to an animated GIF until it is stopped. The frames are
+/// encoded on a separate thread, so recording does not slow down the emulator.
+pub struct GifRecorder {
+    path: PathBuf,
+    pending: Option<(Vec<u8>, Instant)>, // last frame and when it was first displayed
+    sender: Sender<(Vec<u8>, Duration)>,
+    encoder: JoinHandle<ImageResult<()>>,
+}
+
+impl GifRecorder {
+    // Most viewers do not show frames shorter than this, so such frames are skipped
+    const MIN_DELAY: Duration = Duration::from_millis(20);
+
+    pub fn start(
+        path: PathBuf,
+        width: u32,
+        height: u32,
+        scale: u32,
+    ) -> std::io::Result<GifRecorder> {
+        create_parent_dir(&path)?;
+        let file = File::create(&path)?;
+        let (sender, receiver) = mpsc::channel::<(Vec<u8>, Duration)>();
+        let encoder = thread::spawn(move || {
+            let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), 10);
+            encoder.set_repeat(Repeat::Infinite)?;
+            for (data, duration) in receiver {
+                let image = scaled_image(&data, width, height, scale);
+                let delay = Delay::from_numer_denom_ms(duration.as_millis() as u32, 1);
+                encoder.encode_frame(Frame::from_parts(image, 0, 0, delay))?;
+            }
+            Ok(())
+        });
+
+        println!("Recording to '{}'", path.display());
+        Ok(GifRecorder {
+            path,
+            pending: None,
+            sender,
+            encoder,
+        })
+    }
+
+    /// Adds the currently displayed pixels, if they changed since the last frame.
+    pub fn add_frame(&mut self, data: &[u8]) {
+        self.add_frame_at(data, Instant::now());
+    }
+
+    fn add_frame_at(&mut self, data: &[u8], now: Instant) {
+        let mut shown = now;
+        if let Some((pending, pending_shown)) = self.pending.take() {
+            if pending.as_slice() == data {
+                self.pending = Some((pending, pending_shown));
+                return;
+            }
+            let duration = now - pending_shown;
+            if duration >= GifRecorder::MIN_DELAY {
+                // GIF delays are in hundredths of a second, the rest is added to the next frame
+                let delay = Duration::from_millis(duration.as_millis() as u64 / 10 * 10);
+                self.send(pending, delay);
+                shown = now - (duration - delay);
+            } else {
+                // The skipped frame's time is added to the next frame, so the GIF keeps its pace
+                shown = pending_shown;
+            }
+        }
+        self.pending = Some((data.to_vec(), shown));
+    }
+
+    /// Writes the last frame and waits for the file to be finished.
+    pub fn stop(mut self) {
+        if let Some((pending, shown)) = self.pending.take() {
+            self.send(pending, shown.elapsed().max(GifRecorder::MIN_DELAY));
+        }
+
+        let GifRecorder {
+            path,
+            sender,
+            encoder,
+            ..
+        } = self;
+        drop(sender);
+        match encoder.join() {
+            Ok(Ok(())) => println!("Saved recording to '{}'", path.display()),
+            Ok(Err(e)) => eprintln!(
+                "Failed to save recording: '{0}', [ERROR]: {1}",
+                path.display(),
+                e
+            ),
+            Err(_) => eprintln!("Failed to save recording: '{0}'", path.display()),
+        }
+    }
+
+    fn send(&self, data: Vec<u8>, duration: Duration) {
+        // The encoder only stops early on errors, which are reported by `stop`
+        let _ = self.sender.send((data, duration));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::Receiver;
+
+    fn recorder() -> (GifRecorder, Receiver<(Vec<u8>, Duration)>) {
+        let (sender, receiver) = mpsc::channel();
+        let recorder = GifRecorder {
+            path: PathBuf::new(),
+            pending: None,
+            sender,
+            encoder: thread::spawn(|| Ok(())),
+        };
+        (recorder, receiver)
+    }
+
+    #[test]
+    fn short_frames_add_their_time_to_the_next() {
+        let (mut recorder, receiver) = recorder();
+        let start = Instant::now();
+        recorder.add_frame_at(&[0], start);
+        recorder.add_frame_at(&[1], start + Duration::from_millis(5));
+        recorder.add_frame_at(&[2], start + Duration::from_millis(30));
+        recorder.add_frame_at(&[3], start + Duration::from_millis(60));
+
+        let frames: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(
+            frames,
+            vec![
+                (vec![1], Duration::from_millis(30)),
+                (vec![2], Duration::from_millis(30)),
+            ]
+        );
+    }
+
+    #[test]
+    fn delays_keep_the_rounded_off_time() {
+        let (mut recorder, receiver) = recorder();
+        let start = Instant::now();
+        for frame in 0..4u8 {
+            recorder.add_frame_at(&[frame], start + Duration::from_millis(25 * frame as u64));
+        }
+
+        let total: Duration = receiver.try_iter().map(|(_, delay)| delay).sum();
+        assert_eq!(total, Duration::from_millis(70));
+    }
+
+    #[test]
+    fn unchanged_frames_are_not_added() {
+        let (mut recorder, receiver) = recorder();
+        let start = Instant::now();
+        recorder.add_frame_at(&[0], start);
+        recorder.add_frame_at(&[0], start + Duration::from_millis(40));
+        recorder.add_frame_at(&[1], start + Duration::from_millis(80));
+
+        let frames: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(frames, vec![(vec![0], Duration::from_millis(80))]);
+    }
+}
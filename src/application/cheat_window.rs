@@ -0,0 +1,255 @@
+use super::emu_thread::EmulatorThread;
+use crate::config::cheats::CheatFile;
+use crate::emulator::cheats::{Cheat, Comparison, Search};
+use crate::emulator::chip8::Emulator;
+use imgui::*;
+
+/// Window for searching RAM and keeping cheats for the running ROM.
+pub struct CheatWindow {
+    pub open: bool,
+    file: CheatFile,
+    hash: Option<String>, // SHA-1 of the ROM the cheats are for
+    cheats: Vec<Cheat>,
+    search: Option<Search>,
+    comparison: usize, // index into COMPARISONS
+    value: i32,        // value to search for with Comparison::Value
+    // Cheat being typed in by hand
+    name: ImString,
+    address: ImString,
+    new_value: i32,
+    freeze: bool,
+}
+
+impl CheatWindow {
+    const COMPARISONS: [(&'static str, Comparison); 5] = [
+        ("Equal", Comparison::Equal),
+        ("Changed", Comparison::Changed),
+        ("Increased", Comparison::Increased),
+        ("Decreased", Comparison::Decreased),
+        ("Value", Comparison::Value(0)), // compared with `value` instead
+    ];
+    const MAX_LISTED: usize = 50; // candidates shown once the search is narrowed down to these
+
+    pub fn new() -> CheatWindow {
+        CheatWindow {
+            open: false,
+            file: CheatFile::load(),
+            hash: None,
+            cheats: Vec::new(),
+            search: None,
+            comparison: 0,
+            value: 0,
+            name: ImString::with_capacity(32),
+            address: ImString::with_capacity(4),
+            new_value: 0,
+            freeze: true,
+        }
+    }
+
+    /// Switches to the cheats of a ROM that was just loaded, freezing the enabled ones.
+    pub fn set_rom(&mut self, hash: &str, emulator: &mut Emulator) {
+        self.hash = Some(hash.to_string());
+        self.cheats = self.file.cheats(hash).to_vec();
+        self.search = None;
+        self.update_frozen(emulator);
+    }
+
+    fn update_frozen(&self, emulator: &mut Emulator) {
+        emulator.frozen = self
+            .cheats
+            .iter()
+            .filter(|cheat| cheat.freeze && cheat.enabled)
+            .map(|cheat| (cheat.address, cheat.value))
+            .collect();
+    }
+
+    // Saves the cheats after they were changed and applies them to the running ROM
+    fn commit(&mut self, emulator: &mut Emulator) {
+        if let Some(hash) = &self.hash {
+            self.file.set_cheats(hash, self.cheats.clone());
+        }
+        self.update_frozen(emulator);
+    }
+
+    pub fn render(&mut self, ui: &Ui, emulator: &EmulatorThread) {
+        if !self.open {
+            return;
+        }
+        // Only locked while the window is open
+        let mut emulator = emulator.lock();
+        let emulator: &mut Emulator = &mut emulator;
+
+        let mut open = self.open;
+        imgui::Window::new(im_str!("Cheats"))
+            .size([320.0, 420.0], Condition::FirstUseEver)
+            .position([400.0, 120.0], Condition::FirstUseEver)
+            .opened(&mut open)
+            .build(ui, || {
+                if self.hash.is_none() {
+                    ui.text_disabled("Start a ROM to use cheats.");
+                    return;
+                }
+                if CollapsingHeader::new(im_str!("Search"))
+                    .default_open(true)
+                    .build(ui)
+                {
+                    self.render_search(ui, emulator);
+                }
+                if CollapsingHeader::new(im_str!("Cheats"))
+                    .default_open(true)
+                    .build(ui)
+                {
+                    self.render_cheats(ui, emulator);
+                }
+            });
+        self.open = open;
+    }
+
+    fn render_search(&mut self, ui: &Ui, emulator: &mut Emulator) {
+        if ui.button(im_str!("New search"), [0.0, 0.0]) {
+            self.search = Some(Search::new(&emulator.ram));
+        }
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Take a snapshot of RAM with every address as a candidate");
+        }
+        let search = match &mut self.search {
+            Some(search) => search,
+            None => return,
+        };
+
+        ui.set_next_item_width(100.0);
+        ComboBox::new(im_str!("##comparison")).build_simple(
+            ui,
+            &mut self.comparison,
+            &CheatWindow::COMPARISONS,
+            &|(label, _)| ImString::new(*label).into(),
+        );
+        let comparison = match CheatWindow::COMPARISONS[self.comparison].1 {
+            Comparison::Value(_) => {
+                ui.same_line(0.0);
+                ui.set_next_item_width(80.0);
+                if ui.input_int(im_str!("##value"), &mut self.value).build() {
+                    self.value = self.value.clamp(0, 255);
+                }
+                Comparison::Value(self.value as u8)
+            }
+            comparison => comparison,
+        };
+        ui.same_line(0.0);
+        if ui.button(im_str!("Narrow"), [0.0, 0.0]) {
+            search.narrow(&emulator.ram, comparison);
+        }
+
+        let candidates = search.candidates();
+        ui.text(format!("{} candidates", candidates.len()));
+        if candidates.len() > CheatWindow::MAX_LISTED {
+            ui.text_disabled(format!(
+                "Narrow down to {} to list them.",
+                CheatWindow::MAX_LISTED
+            ));
+            return;
+        }
+
+        let mut added = None;
+        for address in candidates {
+            let id = ui.push_id(*address as i32);
+            let value = emulator.ram[*address as usize];
+            ui.text(format!(
+                "{:03X}: {:3} -> {:3}",
+                address,
+                search.previous(*address),
+                value
+            ));
+            ui.same_line(0.0);
+            if ui.small_button(im_str!("Add")) {
+                added = Some(Cheat {
+                    name: format!("{:03X}", address),
+                    address: *address,
+                    value,
+                    freeze: true,
+                    enabled: false,
+                });
+            }
+            id.pop(ui);
+        }
+        if let Some(cheat) = added {
+            self.cheats.push(cheat);
+            self.commit(emulator);
+        }
+    }
+
+    fn render_cheats(&mut self, ui: &Ui, emulator: &mut Emulator) {
+        let mut changed = false;
+        let mut removed = None;
+        for (index, cheat) in self.cheats.iter_mut().enumerate() {
+            let id = ui.push_id(index as i32);
+            if cheat.freeze {
+                changed |= ui.checkbox(im_str!("##enabled"), &mut cheat.enabled);
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("Freeze");
+                }
+            } else if ui.small_button(im_str!("Poke")) {
+                if let Some(byte) = emulator.ram.get_mut(cheat.address as usize) {
+                    *byte = cheat.value;
+                }
+            }
+            ui.same_line(0.0);
+            ui.text(format!(
+                "{}  {:03X} = {}",
+                cheat.name, cheat.address, cheat.value
+            ));
+            ui.same_line(0.0);
+            if ui.small_button(im_str!("Delete")) {
+                removed = Some(index);
+            }
+            id.pop(ui);
+        }
+        if let Some(index) = removed {
+            self.cheats.remove(index);
+            changed = true;
+        }
+
+        ui.separator();
+        ui.set_next_item_width(150.0);
+        ui.input_text(im_str!("Name"), &mut self.name).build();
+        ui.set_next_item_width(150.0);
+        ui.input_text(im_str!("Address"), &mut self.address)
+            .chars_hexadecimal(true)
+            .build();
+        ui.set_next_item_width(150.0);
+        if ui.input_int(im_str!("Value"), &mut self.new_value).build() {
+            self.new_value = self.new_value.clamp(0, 255);
+        }
+        ui.checkbox(im_str!("Freeze"), &mut self.freeze);
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Set the value every frame instead of once");
+        }
+        let address = u16::from_str_radix(self.address.to_str(), 16)
+            .ok()
+            .filter(|address| (*address as usize) < emulator.ram.len());
+        if let Some(address) = address {
+            ui.same_line(0.0);
+            if ui.button(im_str!("Add"), [0.0, 0.0]) {
+                let name = self.name.to_str().trim();
+                self.cheats.push(Cheat {
+                    name: if name.is_empty() {
+                        format!("{:03X}", address)
+                    } else {
+                        name.to_string()
+                    },
+                    address,
+                    value: self.new_value as u8,
+                    freeze: self.freeze,
+                    enabled: self.freeze,
+                });
+                self.name.clear();
+                self.address.clear();
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.commit(emulator);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use super::palette::Palette;
+use crate::emulator::screen::Screen;
+use serde::{Deserialize, Serialize};
+
+/// Post-processing applied to the screen pixels to hide the flicker of XOR-drawn sprites.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayFilter {
+    None,
+    Persistence, // pixels fade out like on a phosphor screen instead of turning off instantly
+    Blend,       // pixels stay lit if they were lit in the last frame
+}
+
+impl DisplayFilter {
+    pub const ALL: [DisplayFilter; 3] = [
+        DisplayFilter::None,
+        DisplayFilter::Persistence,
+        DisplayFilter::Blend,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DisplayFilter::None => "No filter",
+            DisplayFilter::Persistence => "Persistence",
+            DisplayFilter::Blend => "Frame blend",
+        }
+    }
+}
+
+/// Turns pixel values into colors, keeping the per-pixel state the filters need.
+pub struct FilterState {
+    pub filter: DisplayFilter,
+    pub decay: f32,      // brightness lost per frame by unlit pixels with persistence
+    intensity: Vec<f32>, // brightness of every pixel, for persistence
+    lit: Vec<u8>,        // last non-zero value of every pixel, for persistence
+    previous: Vec<u8>,   // pixels of the last frame, for blending
+    current: Vec<u8>,    // pixels of the frame in progress, for blending
+    frame_time: f32,     // time since the last frame started
+}
+
+impl FilterState {
+    // Filters work in CHIP-8 frames, which are as long as a tick of the 60 Hz timers
+    const FRAME_TIME: f32 = 1.0 / 60.0;
+    pub const DEFAULT_DECAY: f32 = 0.25;
+
+    pub fn new(pixels: usize, filter: DisplayFilter, decay: f32) -> FilterState {
+        FilterState {
+            filter,
+            decay: FilterState::valid_decay(decay),
+            intensity: vec![0.0; pixels],
+            lit: vec![0; pixels],
+            previous: vec![0; pixels],
+            current: vec![0; pixels],
+            frame_time: 0.0,
+        }
+    }
+
+    /// Writes the RGBA color of the pixels in `rows` (bit n for row n) into `data`, `dt`
+    /// seconds after the last call. Animated filters need every row on every call.
+    pub fn apply(
+        &mut self,
+        screen: &Screen,
+        rows: u64,
+        palette: &Palette,
+        data: &mut [u8],
+        dt: f32,
+    ) {
+        self.frame_time += dt;
+        let new_frame = self.frame_time >= FilterState::FRAME_TIME;
+        if new_frame {
+            self.frame_time %= FilterState::FRAME_TIME;
+        }
+        // Frame rate independent fading
+        let fade = (1.0 - self.decay).powf(dt / FilterState::FRAME_TIME);
+
+        let changed = (0..Screen::HEIGHT).filter(|y| rows >> y & 1 == 1);
+        for (x, y) in changed.flat_map(|y| (0..Screen::WIDTH).map(move |x| (x, y))) {
+            let i = y * Screen::WIDTH + x;
+            let value = screen.get_pixel(x, y);
+            let color = match self.filter {
+                DisplayFilter::None => palette.rgba(value),
+                DisplayFilter::Persistence => {
+                    if value != 0 {
+                        self.intensity[i] = 1.0;
+                        self.lit[i] = value;
+                    } else {
+                        self.intensity[i] *= fade;
+                    }
+                    FilterState::mix(
+                        palette.rgba(0),
+                        palette.rgba(self.lit[i]),
+                        self.intensity[i],
+                    )
+                }
+                DisplayFilter::Blend => {
+                    if new_frame {
+                        self.previous[i] = self.current[i];
+                        self.current[i] = 0;
+                    }
+                    self.current[i] |= value;
+                    palette.rgba(self.current[i] | self.previous[i])
+                }
+            };
+            data[i * 4..i * 4 + 4].copy_from_slice(&color);
+        }
+    }
+
+    /// `decay` limited to 0 to 1, as more would turn the fading brightness into NaN.
+    pub fn valid_decay(decay: f32) -> f32 {
+        if decay.is_nan() {
+            FilterState::DEFAULT_DECAY
+        } else {
+            decay.clamp(0.0, 1.0)
+        }
+    }
+
+    /// Whether the filter changes the picture even when the screen is not redrawn.
+    pub fn is_animated(&self) -> bool {
+        self.filter != DisplayFilter::None
+    }
+
+    fn mix(from: [u8; 4], to: [u8; 4], amount: f32) -> [u8; 4] {
+        let mut color = [0; 4];
+        for (i, channel) in color.iter_mut().enumerate() {
+            *channel = (from[i] as f32 + (to[i] as f32 - from[i] as f32) * amount).round() as u8;
+        }
+        color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decay_is_limited_to_a_fraction() {
+        assert_eq!(FilterState::valid_decay(0.5), 0.5);
+        assert_eq!(FilterState::valid_decay(3.0), 1.0);
+        assert_eq!(FilterState::valid_decay(-1.0), 0.0);
+        assert_eq!(
+            FilterState::valid_decay(f32::NAN),
+            FilterState::DEFAULT_DECAY
+        );
+    }
+
+    #[test]
+    fn persistence_fades_with_an_invalid_decay() {
+        let pixels = Screen::WIDTH * Screen::HEIGHT;
+        let mut state = FilterState::new(pixels, DisplayFilter::Persistence, 5.0);
+        let palette = Palette::default();
+        let mut data = vec![0; pixels * 4];
+        let mut screen = Screen::new();
+        screen.draw((0, 0), &[0x80], false);
+        state.apply(
+            &screen,
+            u64::MAX,
+            &palette,
+            &mut data,
+            FilterState::FRAME_TIME,
+        );
+        assert_eq!(data[0..4], palette.rgba(1));
+
+        screen.clear();
+        state.apply(
+            &screen,
+            u64::MAX,
+            &palette,
+            &mut data,
+            FilterState::FRAME_TIME,
+        );
+        assert_eq!(data[0..4], palette.rgba(0));
+    }
+}
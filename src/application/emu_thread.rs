@@ -0,0 +1,288 @@
+use crate::emulator::chip8::Emulator;
+use crate::emulator::platform::Detection;
+use crate::emulator::screen::Screen;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Input for the emulator thread.
+pub enum Input {
+    Key(char, bool), // keyboard character and whether it is pressed
+    Turbo(bool),     // run as fast as possible while set
+    Quit,
+}
+
+/// A finished picture, sent to the UI whenever the screen changed.
+pub struct Frame {
+    pub screen: Screen,
+    pub dirty_rows: u64, // rows changed since the last frame
+}
+
+/// A copy of what the debug windows show, taken so the emulator is only locked briefly.
+pub struct CpuState {
+    pub pc: u16,
+    pub i: u16,
+    pub v: [u8; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub stack: Vec<u16>,
+    pub ram: Vec<u8>,
+    pub code: (usize, usize),   // start and end of the loaded ROM
+    pub hits: Option<Vec<u64>>, // executions of each address while the profiler is on
+    pub smc_writes: usize,      // writes into executed code in the log
+    pub bindings: [char; 16],   // keyboard character bound to each key
+    pub detect_quirks: bool,
+    pub detection: Option<Detection>,
+}
+
+impl CpuState {
+    fn new(emulator: &Emulator) -> CpuState {
+        CpuState {
+            pc: emulator.pc,
+            i: emulator.i,
+            v: emulator.v,
+            delay_timer: emulator.delay_timer,
+            sound_timer: emulator.sound_timer,
+            stack: emulator.stack.clone(),
+            ram: emulator.ram.to_vec(),
+            code: emulator.code_memory_location(),
+            hits: emulator
+                .profiler
+                .as_ref()
+                .map(|profiler| (0..emulator.ram.len()).map(|a| profiler.hits(a)).collect()),
+            smc_writes: emulator.smc_writes.len(),
+            bindings: emulator.keyboard.bindings(),
+            detect_quirks: emulator.detect_quirks,
+            detection: emulator.detection.clone(),
+        }
+    }
+}
+
+/// Runs the emulator on its own thread, so a slow UI frame does not hold up the CPU and the
+/// timers. Input goes in and frames come out over channels, the debug windows lock the
+/// emulator to look at and change its state.
+pub struct EmulatorThread {
+    emulator: Arc<Mutex<Emulator>>,
+    inputs: Sender<Input>,
+    frames: Receiver<Frame>,
+    handle: Option<JoinHandle<()>>,
+    finished: Arc<AtomicBool>, // set once the thread stopped, also when it panicked
+    crash: Option<String>,     // why the thread stopped, if it panicked
+}
+
+// Sets the flag when the thread leaves `run`, returning or unwinding
+struct Finished(Arc<AtomicBool>);
+
+impl Drop for Finished {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+impl EmulatorThread {
+    // How often the emulator catches up with the real time
+    const TICK: Duration = Duration::from_millis(2);
+    // Longest time the emulator is locked for at once in turbo mode
+    const TURBO_SLICE: Duration = Duration::from_millis(4);
+    // Time the emulator is left unlocked between turbo slices, as the mutex is not fair and
+    // the UI would hardly ever get it otherwise
+    const TURBO_REST: Duration = Duration::from_micros(500);
+
+    pub fn spawn(emulator: Emulator) -> EmulatorThread {
+        let emulator = Arc::new(Mutex::new(emulator));
+        let (inputs, frames, handle, finished) = EmulatorThread::start(&emulator);
+        EmulatorThread {
+            emulator,
+            inputs,
+            frames,
+            handle: Some(handle),
+            finished,
+            crash: None,
+        }
+    }
+
+    fn start(
+        emulator: &Arc<Mutex<Emulator>>,
+    ) -> (
+        Sender<Input>,
+        Receiver<Frame>,
+        JoinHandle<()>,
+        Arc<AtomicBool>,
+    ) {
+        let (inputs, input_receiver) = mpsc::channel();
+        let (frame_sender, frames) = mpsc::channel();
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let shared = Arc::clone(emulator);
+        let flag = Finished(Arc::clone(&finished));
+        let handle = thread::Builder::new()
+            .name("emulator".to_string())
+            .spawn(move || {
+                let _flag = flag;
+                EmulatorThread::run(&shared, &input_receiver, &frame_sender)
+            })
+            .expect("Failed to start the emulator thread");
+        (inputs, frames, handle, finished)
+    }
+
+    fn run(emulator: &Mutex<Emulator>, inputs: &Receiver<Input>, frames: &Sender<Frame>) {
+        let mut turbo = false;
+        let mut last_tick = Instant::now();
+        loop {
+            for input in inputs.try_iter() {
+                match input {
+                    Input::Key(c, pressed) => {
+                        let mut emulator = lock(emulator);
+                        if let Some(key) = emulator.keyboard.map_key(c) {
+                            emulator.keyboard.set(key, pressed);
+                        }
+                    }
+                    Input::Turbo(on) => turbo = on,
+                    Input::Quit => return,
+                }
+            }
+
+            let now = Instant::now();
+            let dt = now - last_tick;
+            last_tick = now;
+
+            let mut emulator = lock(emulator);
+            if turbo {
+                // Run whole frames for a while, then give the UI a chance to lock. A breakpoint
+                // pauses in the middle of a frame, so the next one must not run past it
+                while !emulator.pause && now.elapsed() < EmulatorThread::TURBO_SLICE {
+                    emulator.advance_frame();
+                }
+            } else {
+                emulator.execute_cycle(dt.as_secs_f32());
+            }
+
+            if emulator.screen.is_dirty() {
+                let dirty_rows = emulator.screen.take_dirty_rows();
+                let frame = Frame {
+                    screen: emulator.screen.clone(),
+                    dirty_rows,
+                };
+                if frames.send(frame).is_err() {
+                    return;
+                }
+            }
+            drop(emulator);
+
+            thread::sleep(if turbo {
+                EmulatorThread::TURBO_REST
+            } else {
+                EmulatorThread::TICK
+            });
+        }
+    }
+
+    /// Locks the emulator, e.g. to read its state or load a ROM.
+    pub fn lock(&self) -> MutexGuard<'_, Emulator> {
+        lock(&self.emulator)
+    }
+
+    /// Copies the state the debug windows show.
+    pub fn cpu_state(&self) -> CpuState {
+        CpuState::new(&self.lock())
+    }
+
+    /// Returns the emulator shared with the thread, for other threads to lock.
+    pub fn shared(&self) -> Arc<Mutex<Emulator>> {
+        Arc::clone(&self.emulator)
+    }
+
+    pub fn send(&self, input: Input) {
+        // The thread only stops after `Quit`
+        let _ = self.inputs.send(input);
+    }
+
+    /// Returns the newest frame since the last call, if the screen changed.
+    pub fn take_frame(&self) -> Option<Frame> {
+        self.frames
+            .try_iter()
+            .fold(None, |merged: Option<Frame>, frame| {
+                let dirty_rows = merged.map_or(0, |merged| merged.dirty_rows);
+                Some(Frame {
+                    screen: frame.screen,
+                    dirty_rows: dirty_rows | frame.dirty_rows,
+                })
+            })
+    }
+
+    /// Returns why the thread stopped, if it panicked. The emulator does not run anymore until
+    /// the thread is restarted.
+    pub fn crash(&mut self) -> Option<&str> {
+        if self.handle.is_some() && self.finished.load(Ordering::Acquire) {
+            if let Some(Err(panic)) = self.handle.take().map(JoinHandle::join) {
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown error".to_string());
+                eprintln!("The emulator stopped, [ERROR]: {}", message);
+                self.crash = Some(message);
+            }
+        }
+        self.crash.as_deref()
+    }
+
+    /// Starts the thread again after it stopped, on the emulator as it was left.
+    pub fn restart(&mut self) {
+        self.stop();
+        let (inputs, frames, handle, finished) = EmulatorThread::start(&self.emulator);
+        self.inputs = inputs;
+        self.frames = frames;
+        self.handle = Some(handle);
+        self.finished = finished;
+        self.crash = None;
+    }
+
+    /// Stops the thread and waits for it to finish.
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.send(Input::Quit);
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for EmulatorThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// A panic in the emulator should not take the UI down with it
+pub fn lock(emulator: &Mutex<Emulator>) -> MutexGuard<'_, Emulator> {
+    emulator.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turbo_stops_at_breakpoints() {
+        let mut emulator = Emulator::new();
+        // 0x200: add 1 to V0, 0x202: jump back
+        emulator.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        emulator.breakpoints.insert(0x202);
+        let mut thread = EmulatorThread::spawn(emulator);
+        thread.send(Input::Turbo(true));
+
+        let start = Instant::now();
+        while !thread.lock().pause && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        thread::sleep(Duration::from_millis(50));
+        let emulator = thread.lock();
+        assert!(emulator.pause);
+        assert_eq!((emulator.pc, emulator.v[0]), (0x202, 1));
+        drop(emulator);
+        thread.stop();
+        assert!(thread.crash().is_none());
+    }
+}
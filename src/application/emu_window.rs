@@ -1,3 +1,9 @@
+use super::capture::{self, GifRecorder};
+use super::display_filter::{DisplayFilter, FilterState};
+use super::emu_thread::{EmulatorThread, Frame};
+use super::layout::Layout;
+use super::palette::Palette;
+use crate::config::settings::Settings;
 use crate::emulator::chip8::Emulator;
 use crate::emulator::screen::Screen;
 use crate::imgui_wgpu_backend::{Renderer, Texture, TextureConfig};
@@ -7,18 +13,9 @@ use wgpu::{
     TextureFormat, TextureUsage,
 };
 
-pub struct RGBA {
-    pub r: f32,
-    pub g: f32,
-    pub b: f32,
-    pub a: f32,
-}
+pub struct RGBA;
 
 impl RGBA {
-    pub fn to_array(&self) -> [f32; 4] {
-        [self.r, self.g, self.b, self.a]
-    }
-
     pub fn to_rgba_normalized(color: [i32; 4]) -> [f32; 4] {
         return [
             (color[0] as f32) / 255.0,
@@ -29,28 +26,47 @@ impl RGBA {
     }
 }
 
+// A button pressed below the display, applied once the window is built
+enum Control {
+    Pause,
+    Start,
+    Step,
+    Frame,
+}
+
 pub struct EmulatorWindow {
     data: Vec<u8>,
     width: usize,
     height: usize,
-    scale: f32,
-    color: RGBA,
+    scale: f32,            // current size of a CHIP-8 pixel on screen
+    integer_scaling: bool, // only scale the display by whole numbers
+    keep_aspect: bool,     // keep the display at the 2:1 aspect ratio
+    palette: Palette,
+    filter: FilterState,
+    screen: Screen, // the last frame received from the emulator
+    redraw: bool,   // redraw every row, e.g. after the colors changed
+    recorder: Option<GifRecorder>,
     tex_id: TextureId,
 }
 
 impl EmulatorWindow {
-    pub fn new(renderer: &mut Renderer, device: &Device) -> EmulatorWindow {
+    pub fn new(renderer: &mut Renderer, device: &Device, settings: &Settings) -> EmulatorWindow {
         EmulatorWindow {
             data: vec![0; Screen::WIDTH * Screen::HEIGHT * 4],
             width: Screen::WIDTH,
             height: Screen::HEIGHT,
-            scale: 11.0f32,
-            color: RGBA {
-                r: 0.0f32,
-                g: 0.76f32,
-                b: 0.02f32,
-                a: 1.0f32,
-            },
+            scale: 1.0,
+            integer_scaling: settings.integer_scaling,
+            keep_aspect: settings.keep_aspect,
+            palette: settings.palette,
+            filter: FilterState::new(
+                Screen::WIDTH * Screen::HEIGHT,
+                settings.display_filter,
+                settings.decay,
+            ),
+            screen: Screen::new(),
+            redraw: true,
+            recorder: None,
             tex_id: EmulatorWindow::create_texture(
                 renderer,
                 device,
@@ -60,68 +76,346 @@ impl EmulatorWindow {
         }
     }
 
-    pub fn render(&mut self, ui: &imgui::Ui, emulator: &mut Emulator, dt: f32) {
-        let win = imgui::Window::new(im_str!("Emulator Window")).resizable(false);
-        win.position([5.0f32, 5.0f32], imgui::Condition::Once)
-            .build(&ui, || {
-                Image::new(
-                    self.tex_id,
-                    [
-                        (self.width as f32) * self.scale,
-                        (self.height as f32) * self.scale,
-                    ],
-                )
-                .tint_col(self.color.to_array())
-                .build(&ui);
-
-                if ui.button(im_str!("PAUSE"), [0f32, 0f32]) {
-                    emulator.pause = true;
+    /// Stores the display options in the settings.
+    pub fn save_settings(&self, settings: &mut Settings) {
+        settings.integer_scaling = self.integer_scaling;
+        settings.keep_aspect = self.keep_aspect;
+        settings.palette = self.palette;
+        settings.display_filter = self.filter.filter;
+        settings.decay = self.filter.decay;
+    }
+
+    /// Saves the display as it is shown to a PNG, named after `name`.
+    pub fn save_screenshot(&self, name: &str) {
+        capture::save_screenshot(
+            &self.data,
+            self.width as u32,
+            self.height as u32,
+            self.scale.round() as u32,
+            &capture::capture_path(name, "png"),
+        );
+    }
+
+    /// Starts recording the display to a GIF named after `name`, or saves the running recording.
+    pub fn toggle_recording(&mut self, name: &str) {
+        if self.recorder.is_some() {
+            self.stop_recording();
+            return;
+        }
+
+        let path = capture::capture_path(name, "gif");
+        match GifRecorder::start(
+            path.clone(),
+            self.width as u32,
+            self.height as u32,
+            self.scale.round() as u32,
+        ) {
+            Ok(mut recorder) => {
+                recorder.add_frame(&self.data);
+                self.recorder = Some(recorder);
+            }
+            Err(e) => eprintln!(
+                "Failed to start recording: '{0}', [ERROR]: {1}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            recorder.stop();
+        }
+    }
+
+    /// Draws the display and its controls, scaled to fit the window. In game mode only the
+    /// display is drawn, covering the whole screen. Returns true if the user asked to switch
+    /// game mode on or off.
+    pub fn render(
+        &mut self,
+        ui: &imgui::Ui,
+        emulator: &EmulatorThread,
+        layout: &Layout,
+        game_mode: bool,
+    ) -> bool {
+        let mut toggle_game_mode = false;
+        // The emulator is not kept locked while the window is built, so it keeps running
+        let old_speed = emulator.lock().speed;
+        let mut speed = old_speed;
+        let mut control = None;
+
+        let win = imgui::Window::new(im_str!("Emulator Window"));
+        let win = if game_mode {
+            win.position([0.0, 0.0], Condition::Always)
+                .size(ui.io().display_size, Condition::Always)
+                .no_decoration()
+                .movable(false)
+                .save_settings(false)
+        } else {
+            layout.place(win, &layout.emulator)
+        };
+
+        let style = if game_mode {
+            Some((
+                ui.push_style_color(StyleColor::WindowBg, self.palette.colors[0]),
+                ui.push_style_vars(&[
+                    StyleVar::WindowPadding([0.0, 0.0]),
+                    StyleVar::WindowBorderSize(0.0),
+                ]),
+            ))
+        } else {
+            None
+        };
+
+        win.build(&ui, || {
+            // Two rows of controls below the display
+            let controls_height = if game_mode {
+                0.0
+            } else {
+                2.0 * ui.frame_height_with_spacing()
+            };
+            let [width, height] = ui.content_region_avail();
+            let size = self.fit([width, height - controls_height]);
+
+            // Center the display in the space left for it
+            let [x, y] = ui.cursor_pos();
+            let y_offset = if game_mode {
+                (height - size[1]) / 2.0
+            } else {
+                0.0
+            };
+            ui.set_cursor_pos([
+                x + ((width - size[0]) / 2.0).max(0.0),
+                y + y_offset.max(0.0),
+            ]);
+            Image::new(self.tex_id, size).build(&ui);
+            if self.recorder.is_some() {
+                let [x, y] = ui.item_rect_min();
+                let draw_list = ui.get_window_draw_list();
+                draw_list
+                    .add_circle([x + 14.0, y + 14.0], 6.0, [1.0, 0.0, 0.0, 1.0])
+                    .filled(true)
+                    .build();
+                draw_list.add_text([x + 24.0, y + 7.0], [1.0, 0.0, 0.0, 1.0], "REC");
+            }
+            if ui.is_item_clicked(MouseButton::Right) {
+                ui.open_popup(im_str!("display_options"));
+            }
+            ui.popup(im_str!("display_options"), || {
+                ui.checkbox(im_str!("Integer scaling"), &mut self.integer_scaling);
+                ui.checkbox(im_str!("Keep aspect ratio"), &mut self.keep_aspect);
+                ui.separator();
+                let label = if game_mode {
+                    im_str!("Leave game mode (F11)")
+                } else {
+                    im_str!("Game mode (F11)")
+                };
+                if Selectable::new(label).build(ui) {
+                    toggle_game_mode = true;
                 }
-                ui.same_line(0.0f32);
-                if ui.button(im_str!("START"), [0f32, 0f32]) {
-                    emulator.pause = false;
+            });
+
+            if game_mode {
+                return;
+            }
+
+            if ui.button(im_str!("PAUSE"), [0f32, 0f32]) {
+                control = Some(Control::Pause);
+            }
+            ui.same_line(0.0f32);
+            if ui.button(im_str!("START"), [0f32, 0f32]) {
+                control = Some(Control::Start);
+            }
+            ui.same_line(0.0f32);
+            if ui.button(im_str!("STEP"), [0f32, 0f32]) {
+                control = Some(Control::Step);
+            }
+            ui.same_line(0.0f32);
+            if ui.button(im_str!("FRAME"), [0f32, 0f32]) {
+                control = Some(Control::Frame);
+            }
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Run until the next 60 Hz frame");
+            }
+
+            ui.same_line(0.0f32);
+            ui.set_next_item_width(150.0);
+            Slider::new(im_str!("Speed"))
+                .range(Emulator::MIN_SPEED..=Emulator::MAX_SPEED)
+                .display_format(im_str!("%.2fx"))
+                .flags(SliderFlags::LOGARITHMIC | SliderFlags::ALWAYS_CLAMP)
+                .build(ui, &mut speed);
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Double-click to type a speed, hold Tab for turbo");
+            }
+            ui.same_line(0.0f32);
+            if ui.small_button(im_str!("1x")) {
+                speed = 1.0;
+            }
+
+            if self.render_palette(ui) {
+                // Redraw the screen texture with the new colors
+                self.redraw = true;
+            }
+
+            ui.same_line(0.0f32);
+            if self.render_filter(ui) {
+                self.redraw = true;
+            }
+        });
+
+        if let Some((color, vars)) = style {
+            vars.pop(ui);
+            color.pop(ui);
+        }
+
+        if control.is_some() || speed != old_speed {
+            let mut emulator = emulator.lock();
+            emulator.speed = speed;
+            match control {
+                Some(Control::Pause) => emulator.pause = true,
+                Some(Control::Start) => emulator.pause = false,
+                Some(Control::Step) => {
+                    emulator.pause = true;
+                    emulator.step();
                 }
-                ui.same_line(0.0f32);
-                if ui.button(im_str!("STEP"), [0f32, 0f32]) {
-                    emulator.pause = false;
-                    emulator.execute_cycle(dt);
+                Some(Control::Frame) => {
                     emulator.pause = true;
+                    emulator.advance_frame();
                 }
+                None => (),
+            }
+        }
+        toggle_game_mode
+    }
 
-                ui.same_line(0.0f32);
-                let mut color = self.color.to_array();
-                imgui::ColorEdit::new(im_str!("Main Color"), &mut color).build(&ui);
-                self.color = RGBA {
-                    r: color[0],
-                    g: color[1],
-                    b: color[2],
-                    a: color[3],
-                };
+    // Size of the display scaled to fit `available`, within the scaling options
+    fn fit(&mut self, available: [f32; 2]) -> [f32; 2] {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let mut scale_x = (available[0] / width).max(1.0);
+        let mut scale_y = (available[1] / height).max(1.0);
+        if self.keep_aspect {
+            scale_x = scale_x.min(scale_y);
+            scale_y = scale_x;
+        }
+        if self.integer_scaling {
+            scale_x = scale_x.floor();
+            scale_y = scale_y.floor();
+        }
+        self.scale = scale_x.min(scale_y);
+        [width * scale_x, height * scale_y]
+    }
+
+    // Flicker filter picker and its decay, returns true if the filter changed
+    fn render_filter(&mut self, ui: &imgui::Ui) -> bool {
+        let mut index = DisplayFilter::ALL
+            .iter()
+            .position(|filter| *filter == self.filter.filter)
+            .unwrap_or(0);
+        ui.set_next_item_width(110.0);
+        let mut changed = ComboBox::new(im_str!("##filter")).build_simple(
+            ui,
+            &mut index,
+            &DisplayFilter::ALL,
+            &|filter| ImString::new(filter.label()).into(),
+        );
+        if changed {
+            self.filter.filter = DisplayFilter::ALL[index];
+        }
+
+        if self.filter.filter == DisplayFilter::Persistence {
+            ui.same_line(0.0f32);
+            ui.set_next_item_width(90.0);
+            changed |= Slider::new(im_str!("Decay"))
+                .range(0.05..=0.95)
+                .display_format(im_str!("%.2f"))
+                .build(ui, &mut self.filter.decay);
+        }
+        changed
+    }
+
+    // Preset picker and color editors, returns true if the palette changed
+    fn render_palette(&mut self, ui: &imgui::Ui) -> bool {
+        let mut changed = false;
+
+        let preview = ImString::new(self.palette.preset_name().unwrap_or("Custom"));
+        ui.set_next_item_width(140.0);
+        ComboBox::new(im_str!("##palette"))
+            .preview_value(&preview)
+            .build(ui, || {
+                for (name, preset) in Palette::presets().iter() {
+                    let label = ImString::new(*name);
+                    if Selectable::new(&label)
+                        .selected(*preset == self.palette)
+                        .build(ui)
+                    {
+                        self.palette = *preset;
+                        changed = true;
+                    }
+                }
             });
+
+        for (i, label) in Palette::LABELS.iter().enumerate() {
+            ui.same_line(0.0f32);
+            let label = ImString::new(*label);
+            changed |= ColorEdit::new(&label, &mut self.palette.colors[i])
+                .inputs(false)
+                .label(false)
+                .alpha(false)
+                .build(ui);
+            if ui.is_item_hovered() {
+                ui.tooltip_text(&label);
+            }
+        }
+        changed
     }
 
     pub fn update(
         &mut self,
-        emulator: &Emulator,
+        frame: Option<Frame>,
         renderer: &mut Renderer,
         device: &Device,
-        mut queue: &mut Queue,
+        queue: &mut Queue,
+        dt: f32,
     ) {
-        for x in 0..self.width {
-            for y in 0..self.height {
-                let v = if emulator.screen.get_pixel(x, y) == 1 {
-                    0xFF
-                } else {
-                    0
-                };
+        let mut rows = 0;
+        if let Some(frame) = frame {
+            self.screen = frame.screen;
+            rows = frame.dirty_rows;
+        }
+        if self.redraw || self.filter.is_animated() {
+            self.redraw = false;
+            rows = Screen::ALL_ROWS;
+        }
+        if rows == 0 {
+            return;
+        }
+
+        self.filter
+            .apply(&self.screen, rows, &self.palette, &mut self.data, dt);
+        if let Some(recorder) = &mut self.recorder {
+            recorder.add_frame(&self.data);
+        }
 
-                let pos = (y * 4 * self.width) + (x * 4);
-                self.data[pos..pos + 4].copy_from_slice(&[v, v, v, 0xFF]);
+        // Make sure we have an active encoder.
+        let encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+
+        // Upload every run of changed rows
+        let mut row = 0;
+        while row < self.height {
+            if rows >> row & 1 == 0 {
+                row += 1;
+                continue;
+            }
+            let first = row;
+            while row < self.height && rows >> row & 1 == 1 {
+                row += 1;
             }
+            self.update_texture(renderer, queue, first, row - first);
         }
 
-        // Uploaded updated screen texture data
-        self.update_texture(renderer, &device, &mut queue);
+        // Resolve the actual copy process.
+        queue.submit(Some(encoder.finish()));
     }
 
     /// Creates a new wgpu texture made from the imgui font atlas.
@@ -151,40 +445,39 @@ impl EmulatorWindow {
         renderer.textures.insert(texture)
     }
 
-    /// Creates and uploads a new wgpu texture made from the imgui font atlas.
+    /// Uploads `count` rows of the screen texture data, starting at row `first`.
     fn update_texture(
         &mut self,
         renderer: &Renderer,
-        device: &Device,
         queue: &mut Queue,
+        first: usize,
+        count: usize,
     ) -> Option<bool> {
-        // Make sure we have an active encoder.
-        let encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+        let bytes_per_row = self.data.len() / self.height;
 
         queue.write_texture(
             ImageCopyTexture {
                 texture: &renderer.textures.get(self.tex_id)?.texture(),
                 mip_level: 0,
-                origin: Origin3d { x: 0, y: 0, z: 0 },
+                origin: Origin3d {
+                    x: 0,
+                    y: first as u32,
+                    z: 0,
+                },
             },
-            &self.data,
+            &self.data[first * bytes_per_row..(first + count) * bytes_per_row],
             ImageDataLayout {
                 offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(
-                    self.data.len() as u32 / self.height as u32,
-                ),
-                rows_per_image: std::num::NonZeroU32::new(self.height as u32),
+                bytes_per_row: std::num::NonZeroU32::new(bytes_per_row as u32),
+                rows_per_image: std::num::NonZeroU32::new(count as u32),
             },
             Extent3d {
                 width: self.width as u32,
-                height: self.height as u32,
+                height: count as u32,
                 depth_or_array_layers: 1,
             },
         );
 
-        // Resolve the actual copy process.
-        queue.submit(Some(encoder.finish()));
-
         Some(true)
     }
 }
@@ -0,0 +1,118 @@
+use crate::library::RomLibrary;
+use imgui::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Window for picking a ROM file anywhere on the filesystem.
+pub struct FileBrowser {
+    pub open: bool,
+    dir: PathBuf,
+    entries: Vec<(PathBuf, bool)>, // path and whether it is a directory
+    error: Option<String>,
+    show_all: bool, // list every file instead of only ROMs
+}
+
+impl FileBrowser {
+    pub fn new() -> FileBrowser {
+        let dir = dirs::home_dir()
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_else(|| PathBuf::from("/"));
+        let mut browser = FileBrowser {
+            open: false,
+            dir: PathBuf::new(),
+            entries: Vec::new(),
+            error: None,
+            show_all: false,
+        };
+        browser.navigate(&dir);
+        browser
+    }
+
+    /// Opens the browser, in the given directory or where it was left last time.
+    pub fn show(&mut self, dir: Option<&Path>) {
+        let dir = dir.map_or_else(|| self.dir.clone(), Path::to_path_buf);
+        self.navigate(&dir);
+        self.open = true;
+    }
+
+    fn navigate(&mut self, dir: &Path) {
+        let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        match fs::read_dir(&dir) {
+            Ok(entries) => {
+                let mut entries: Vec<(PathBuf, bool)> = entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| {
+                        let path = entry.path();
+                        let is_dir = path.is_dir();
+                        (path, is_dir)
+                    })
+                    .collect();
+                // Directories first, then by name
+                entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+                self.dir = dir;
+                self.entries = entries;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(format!("Cannot open '{}': {}", dir.display(), e)),
+        }
+    }
+
+    /// Draws the browser and returns the file the user picked, if any.
+    pub fn render(&mut self, ui: &Ui) -> Option<PathBuf> {
+        if !self.open {
+            return None;
+        }
+
+        let mut selected = None;
+        let mut navigate_to = None;
+        let mut open = self.open;
+        imgui::Window::new(im_str!("Open ROM"))
+            .size([420.0, 400.0], Condition::FirstUseEver)
+            .position([300.0, 100.0], Condition::FirstUseEver)
+            .opened(&mut open)
+            .build(ui, || {
+                if ui.button(im_str!("Up"), [0.0, 0.0]) {
+                    navigate_to = self.dir.parent().map(Path::to_path_buf);
+                }
+                ui.same_line(0.0);
+                ui.text(self.dir.display().to_string());
+                ui.checkbox(im_str!("Show all files"), &mut self.show_all);
+
+                if let Some(error) = &self.error {
+                    ui.text_colored([1.0, 0.4, 0.4, 1.0], error);
+                }
+                ui.separator();
+
+                ChildWindow::new("entries").build(ui, || {
+                    for (path, is_dir) in &self.entries {
+                        if !is_dir && !self.show_all && !RomLibrary::is_rom(path) {
+                            continue;
+                        }
+                        let name = path
+                            .file_name()
+                            .map(|name| name.to_string_lossy().into_owned())
+                            .unwrap_or_default();
+                        let label = if *is_dir {
+                            ImString::new(format!("{}/", name))
+                        } else {
+                            ImString::new(name)
+                        };
+                        if Selectable::new(&label).build(ui) {
+                            if *is_dir {
+                                navigate_to = Some(path.clone());
+                            } else {
+                                selected = Some(path.clone());
+                            }
+                        }
+                    }
+                });
+            });
+
+        if let Some(dir) = navigate_to {
+            self.navigate(&dir);
+        }
+        self.open = open && selected.is_none();
+        selected
+    }
+}
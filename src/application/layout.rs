@@ -0,0 +1,98 @@
+use imgui::{Condition, Window};
+
+/// Position and size of a window.
+#[derive(Clone, Copy)]
+pub struct Rect {
+    pub pos: [f32; 2],
+    pub size: [f32; 2],
+}
+
+/// Default placement of the windows, worked out from the size of the display: the emulator
+/// and About windows on the left, Code and CPU state in the middle and the ROMs on the right.
+pub struct Layout {
+    pub emulator: Rect,
+    pub about: Rect,
+    pub code: Rect,
+    pub cpu: Rect,
+    pub settings: Rect,
+    pub roms: Rect,
+    pub condition: Condition, // when the placement is applied
+}
+
+impl Layout {
+    const MARGIN: f32 = 5.0;
+    const GAP: f32 = 3.0;
+    const MIDDLE_WIDTH: f32 = 300.0;
+    const ROMS_WIDTH: f32 = 363.0;
+    const BOTTOM_HEIGHT: f32 = 210.0;
+
+    /// Layout for a display of `display_size`. With `reset` the windows are moved back to
+    /// their default places, otherwise only windows that were never placed are.
+    pub fn new(display_size: [f32; 2], reset: bool) -> Layout {
+        let [width, height] = display_size;
+        let middle_x =
+            width - Layout::MARGIN - Layout::ROMS_WIDTH - Layout::GAP - Layout::MIDDLE_WIDTH;
+        let left_width = (middle_x - Layout::GAP - Layout::MARGIN).max(200.0);
+        let top_height =
+            (height - 2.0 * Layout::MARGIN - Layout::GAP - Layout::BOTTOM_HEIGHT).max(100.0);
+        let bottom_y = Layout::MARGIN + top_height + Layout::GAP;
+
+        Layout {
+            emulator: Rect {
+                pos: [Layout::MARGIN, Layout::MARGIN],
+                size: [left_width, top_height],
+            },
+            about: Rect {
+                pos: [Layout::MARGIN, bottom_y],
+                size: [left_width, Layout::BOTTOM_HEIGHT],
+            },
+            code: Rect {
+                pos: [middle_x, Layout::MARGIN],
+                size: [Layout::MIDDLE_WIDTH, top_height],
+            },
+            cpu: Rect {
+                pos: [middle_x, bottom_y],
+                size: [Layout::MIDDLE_WIDTH, Layout::BOTTOM_HEIGHT],
+            },
+            settings: Rect {
+                pos: [middle_x, bottom_y - 28.0],
+                size: [Layout::MIDDLE_WIDTH, 130.0],
+            },
+            roms: Rect {
+                pos: [width - Layout::MARGIN - Layout::ROMS_WIDTH, Layout::MARGIN],
+                size: [Layout::ROMS_WIDTH, height - 2.0 * Layout::MARGIN],
+            },
+            condition: if reset {
+                Condition::Always
+            } else {
+                Condition::FirstUseEver
+            },
+        }
+    }
+
+    /// Size of the main window that fits the default layout with the display at `scale`.
+    pub fn window_size(scale: f32, display: [f32; 2]) -> [f32; 2] {
+        // Room taken by the emulator window's title bar, padding and buttons
+        let (extra_width, extra_height) = (16.0, 61.0);
+        [
+            display[0] * scale
+                + extra_width
+                + 2.0 * Layout::MARGIN
+                + 2.0 * Layout::GAP
+                + Layout::MIDDLE_WIDTH
+                + Layout::ROMS_WIDTH,
+            display[1] * scale
+                + extra_height
+                + 2.0 * Layout::MARGIN
+                + Layout::GAP
+                + Layout::BOTTOM_HEIGHT,
+        ]
+    }
+
+    /// Places a window at `rect`.
+    pub fn place<'a>(&self, window: Window<'a>, rect: &Rect) -> Window<'a> {
+        window
+            .position(rect.pos, self.condition)
+            .size(rect.size, self.condition)
+    }
+}
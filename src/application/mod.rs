@@ -1,2 +1,13 @@
+pub mod access_map_window;
 pub mod app;
+pub mod capture;
+pub mod cheat_window;
+pub mod display_filter;
+pub mod emu_thread;
 pub mod emu_window;
+pub mod file_browser;
+pub mod layout;
+pub mod palette;
+pub mod profiler_window;
+pub mod rom_browser;
+pub mod smc_window;
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+/// Display colors, indexed by pixel value. 0 is the off color and 1 the on color, 2 and 3
+/// are used by ROMs drawing to more than one bit plane.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Palette {
+    pub colors: [[f32; 4]; 4],
+}
+
+impl Palette {
+    pub const LABELS: [&'static str; 4] = ["Off", "On", "Plane 2", "Both planes"];
+
+    /// The built-in palettes and their names.
+    pub fn presets() -> [(&'static str, Palette); 5] {
+        [
+            (
+                "Green Phosphor",
+                Palette::from_hex([0x000000, 0x00c205, 0x006103, 0x9cff9f]),
+            ),
+            (
+                "Amber",
+                Palette::from_hex([0x1a0f00, 0xffb000, 0x8a5c00, 0xffe0a0]),
+            ),
+            (
+                "LCD",
+                Palette::from_hex([0xc7cfa0, 0x2b3a2a, 0x76846a, 0x4e5c48]),
+            ),
+            (
+                "Game Boy",
+                Palette::from_hex([0x9bbc0f, 0x0f380f, 0x8bac0f, 0x306230]),
+            ),
+            (
+                "High Contrast",
+                Palette::from_hex([0x000000, 0xffffff, 0xffff00, 0x00ffff]),
+            ),
+        ]
+    }
+
+    /// Name of the preset this palette is equal to, if any.
+    pub fn preset_name(&self) -> Option<&'static str> {
+        Palette::presets()
+            .iter()
+            .find(|(_, preset)| preset == self)
+            .map(|(name, _)| *name)
+    }
+
+    /// Color of a pixel with the given value, as RGBA bytes.
+    pub fn rgba(&self, value: u8) -> [u8; 4] {
+        let color = self.colors[value as usize % self.colors.len()];
+        let mut rgba = [0; 4];
+        for (byte, channel) in rgba.iter_mut().zip(color.iter()) {
+            *byte = (channel.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        rgba
+    }
+
+    fn from_hex(colors: [u32; 4]) -> Palette {
+        let mut palette = Palette {
+            colors: [[0.0; 4]; 4],
+        };
+        for (color, hex) in palette.colors.iter_mut().zip(colors.iter()) {
+            *color = [
+                ((hex >> 16) & 0xFF) as f32 / 255.0,
+                ((hex >> 8) & 0xFF) as f32 / 255.0,
+                (hex & 0xFF) as f32 / 255.0,
+                1.0,
+            ];
+        }
+        palette
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::presets()[0].1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_plane_combination_has_a_color() {
+        let palette = Palette::from_hex([0x000000, 0xff0000, 0x00ff00, 0x0000ff]);
+        assert_eq!(palette.rgba(0), [0, 0, 0, 255]);
+        assert_eq!(palette.rgba(1), [255, 0, 0, 255]);
+        assert_eq!(palette.rgba(2), [0, 255, 0, 255]);
+        assert_eq!(palette.rgba(3), [0, 0, 255, 255]);
+    }
+}
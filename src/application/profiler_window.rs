@@ -0,0 +1,188 @@
+use super::capture;
+use super::emu_thread::EmulatorThread;
+use crate::emulator::chip8::Emulator;
+use crate::emulator::profiler::Profiler;
+use imgui::*;
+use std::fs;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Column {
+    Address,
+    Hits,
+    Calls,
+}
+
+/// Window showing where the running ROM spends its instructions.
+pub struct ProfilerWindow {
+    pub open: bool,
+    sort: Column,
+    descending: bool,
+}
+
+impl ProfilerWindow {
+    const MAX_ROWS: usize = 200; // hottest addresses listed
+
+    pub fn new() -> ProfilerWindow {
+        ProfilerWindow {
+            open: false,
+            sort: Column::Hits,
+            descending: true,
+        }
+    }
+
+    /// Color of code executed `hits` times, from yellow for rarely to red for the most often
+    /// executed. Code that never ran is gray.
+    pub fn heat_color(hits: u64, max_hits: u64) -> [f32; 4] {
+        if hits == 0 {
+            return [0.5, 0.5, 0.5, 1.0];
+        }
+        // On a log scale, so a hot loop does not make everything else look cold
+        let heat = ((hits as f32).ln_1p() / (max_hits.max(1) as f32).ln_1p()).min(1.0);
+        [1.0, 1.0 - 0.8 * heat, 0.6 * (1.0 - heat), 1.0]
+    }
+
+    /// Draws the window, `name` is used for the exported file.
+    pub fn render(&mut self, ui: &Ui, emulator: &EmulatorThread, name: &str) {
+        if !self.open {
+            return;
+        }
+        // Only locked while the window is open
+        let mut emulator = emulator.lock();
+        let emulator: &mut Emulator = &mut emulator;
+
+        let mut open = self.open;
+        imgui::Window::new(im_str!("Profiler"))
+            .size([360.0, 440.0], Condition::FirstUseEver)
+            .position([420.0, 100.0], Condition::FirstUseEver)
+            .opened(&mut open)
+            .build(ui, || {
+                let mut enabled = emulator.profiler.is_some();
+                if ui.checkbox(im_str!("Count instructions"), &mut enabled) {
+                    emulator.profiler = if enabled {
+                        Some(Box::new(Profiler::new(emulator.ram.len())))
+                    } else {
+                        None
+                    };
+                }
+                let profiler = match &mut emulator.profiler {
+                    Some(profiler) => profiler,
+                    None => {
+                        ui.text_disabled("Switched off, so it does not slow the emulator down.");
+                        return;
+                    }
+                };
+                ui.same_line(0.0);
+                if ui.small_button(im_str!("Clear")) {
+                    profiler.clear();
+                }
+                ui.same_line(0.0);
+                if ui.small_button(im_str!("Export CSV")) {
+                    ProfilerWindow::export(profiler, name);
+                }
+                ui.text(format!("{} instructions", profiler.total()));
+
+                if CollapsingHeader::new(im_str!("Instruction classes")).build(ui) {
+                    ProfilerWindow::render_classes(ui, profiler);
+                }
+                if CollapsingHeader::new(im_str!("Hot spots"))
+                    .default_open(true)
+                    .build(ui)
+                {
+                    self.render_hot_spots(ui, profiler, &emulator.ram);
+                }
+            });
+        self.open = open;
+    }
+
+    fn render_classes(ui: &Ui, profiler: &Profiler) {
+        let total = profiler.total().max(1) as f32;
+        for (name, hits) in Profiler::CLASSES.iter().zip(profiler.classes().iter()) {
+            let fraction = *hits as f32 / total;
+            let overlay = ImString::new(format!("{}: {} ({:.1}%)", name, hits, fraction * 100.0));
+            ProgressBar::new(fraction)
+                .size([-1.0, 0.0])
+                .overlay_text(&overlay)
+                .build(ui);
+        }
+    }
+
+    fn render_hot_spots(&mut self, ui: &Ui, profiler: &Profiler, ram: &[u8]) {
+        let mut rows: Vec<(u16, u64, u64)> = profiler
+            .hot_spots()
+            .into_iter()
+            .map(|(address, hits)| (address, hits, profiler.calls(address)))
+            .collect();
+        rows.sort_by_key(|(address, hits, calls)| match self.sort {
+            Column::Address => *address as u64,
+            Column::Hits => *hits,
+            Column::Calls => *calls,
+        });
+        if self.descending {
+            rows.reverse();
+        }
+
+        let total = profiler.total().max(1) as f32;
+        let max_hits = profiler.max_hits();
+        ui.columns(4, im_str!("hot_spots"), true);
+        let headers = [
+            (im_str!("Address"), Some(Column::Address)),
+            (im_str!("Opcode"), None),
+            (im_str!("Hits"), Some(Column::Hits)),
+            (im_str!("Calls"), Some(Column::Calls)),
+        ];
+        for (label, column) in headers.iter() {
+            if let Some(column) = column {
+                if Selectable::new(label)
+                    .selected(self.sort == *column)
+                    .build(ui)
+                {
+                    // Clicking the sorted column again flips the order
+                    self.descending = self.sort != *column || !self.descending;
+                    self.sort = *column;
+                }
+            } else {
+                ui.text(label);
+            }
+            ui.next_column();
+        }
+        ui.separator();
+
+        for (address, hits, calls) in rows.iter().take(ProfilerWindow::MAX_ROWS) {
+            let color = ui.push_style_color(
+                StyleColor::Text,
+                ProfilerWindow::heat_color(*hits, max_hits),
+            );
+            let index = *address as usize;
+            ui.text(format!("{:03X}", address));
+            ui.next_column();
+            ui.text(format!(
+                "{:02X}{:02X}",
+                ram[index],
+                ram.get(index + 1).copied().unwrap_or(0)
+            ));
+            ui.next_column();
+            ui.text(format!("{} ({:.1}%)", hits, *hits as f32 / total * 100.0));
+            ui.next_column();
+            ui.text(calls.to_string());
+            ui.next_column();
+            color.pop(ui);
+        }
+        ui.columns(1, im_str!("hot_spots"), false);
+    }
+
+    fn export(profiler: &Profiler, name: &str) {
+        let path = capture::capture_path(&format!("{}-profile", name), "csv");
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, profiler.to_csv()));
+        match result {
+            Ok(()) => println!("Saved profile to '{}'", path.display()),
+            Err(e) => eprintln!(
+                "Failed to save profile: '{0}', [ERROR]: {1}",
+                path.display(),
+                e
+            ),
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::library::history::History;
+use crate::library::rom::RomInfo;
+use imgui::*;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Name,
+    Author,
+    Year,
+}
+
+impl SortKey {
+    const ALL: [SortKey; 3] = [SortKey::Name, SortKey::Author, SortKey::Year];
+
+    fn label(&self) -> &'static ImStr {
+        match self {
+            SortKey::Name => im_str!("Name"),
+            SortKey::Author => im_str!("Author"),
+            SortKey::Year => im_str!("Year"),
+        }
+    }
+
+    fn compare(&self, a: &RomInfo, b: &RomInfo) -> Ordering {
+        // Missing authors and years are sorted last
+        fn compare_optional(a: &Option<String>, b: &Option<String>) -> Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        }
+
+        let by_name = a
+            .title
+            .to_lowercase()
+            .cmp(&b.title.to_lowercase())
+            .then_with(|| a.path.cmp(&b.path));
+        match self {
+            SortKey::Name => by_name,
+            SortKey::Author => compare_optional(&a.author, &b.author).then(by_name),
+            SortKey::Year => compare_optional(&a.year, &b.year).then(by_name),
+        }
+    }
+}
+
+/// Searchable list of the ROM library grouped by directory, with favorites and recents.
+pub struct RomBrowser {
+    filter: ImString,
+    sort: SortKey,
+}
+
+impl RomBrowser {
+    pub fn new() -> RomBrowser {
+        RomBrowser {
+            filter: ImString::with_capacity(64),
+            sort: SortKey::Name,
+        }
+    }
+
+    /// Draws the browser and returns the path of the ROM the user picked, if any.
+    pub fn render(&mut self, ui: &Ui, roms: &[RomInfo], history: &mut History) -> Option<PathBuf> {
+        let mut selected = None;
+
+        ui.set_next_item_width(200.0);
+        ui.input_text(im_str!("Search"), &mut self.filter).build();
+
+        let mut sort_index = SortKey::ALL
+            .iter()
+            .position(|key| *key == self.sort)
+            .unwrap_or(0);
+        ui.set_next_item_width(200.0);
+        if ComboBox::new(im_str!("Sort by")).build_simple(
+            ui,
+            &mut sort_index,
+            &SortKey::ALL,
+            &|key| key.label().into(),
+        ) {
+            self.sort = SortKey::ALL[sort_index];
+        }
+
+        let filter = self.filter.to_str().trim().to_lowercase();
+        let mut matches: Vec<&RomInfo> = roms
+            .iter()
+            .filter(|rom| RomBrowser::matches(rom, &filter))
+            .collect();
+        matches.sort_by(|a, b| self.sort.compare(a, b));
+
+        TabBar::new(im_str!("##rom_tabs")).build(ui, || {
+            TabItem::new(im_str!("Library")).build(ui, || {
+                let mut categories: BTreeMap<&str, Vec<&RomInfo>> = BTreeMap::new();
+                for rom in matches.iter() {
+                    categories.entry(&rom.category).or_default().push(rom);
+                }
+
+                for (category, roms) in categories {
+                    let id = ImString::new(category);
+                    let label = ImString::new(format!("{} ({})", category, roms.len()));
+
+                    // Expand every category while searching
+                    let condition = if filter.is_empty() {
+                        Condition::Never
+                    } else {
+                        Condition::Always
+                    };
+                    TreeNode::new(&id)
+                        .label(&label)
+                        .opened(true, condition)
+                        .build(ui, || {
+                            for rom in roms {
+                                RomBrowser::render_rom(ui, rom, history, &mut selected);
+                            }
+                        });
+                }
+            });
+
+            TabItem::new(im_str!("Favorites")).build(ui, || {
+                let favorites: Vec<&RomInfo> = matches
+                    .iter()
+                    .filter(|rom| history.is_favorite(&rom.path))
+                    .copied()
+                    .collect();
+                for rom in favorites {
+                    RomBrowser::render_rom(ui, rom, history, &mut selected);
+                }
+                // Favorites that are not part of the library anymore
+                for path in history.favorites().to_vec() {
+                    if !roms.iter().any(|rom| rom.path == path) {
+                        RomBrowser::render_external(ui, &path, &mut selected);
+                    }
+                }
+            });
+
+            TabItem::new(im_str!("Recent")).build(ui, || {
+                for path in history.recent().to_vec() {
+                    match roms.iter().find(|rom| rom.path == path) {
+                        Some(rom) if RomBrowser::matches(rom, &filter) => {
+                            RomBrowser::render_rom(ui, rom, history, &mut selected)
+                        }
+                        Some(_) => (),
+                        None => RomBrowser::render_external(ui, &path, &mut selected),
+                    }
+                }
+            });
+        });
+
+        selected
+    }
+
+    fn matches(rom: &RomInfo, filter: &str) -> bool {
+        if filter.is_empty() {
+            return true;
+        }
+        [
+            Some(&rom.title),
+            rom.author.as_ref(),
+            rom.year.as_ref(),
+            Some(&rom.category),
+        ]
+        .iter()
+        .flatten()
+        .any(|field| field.to_lowercase().contains(filter))
+            || rom.file_name().to_lowercase().contains(filter)
+    }
+
+    fn render_rom(ui: &Ui, rom: &RomInfo, history: &mut History, selected: &mut Option<PathBuf>) {
+        let id = ui.push_id(rom.path.to_string_lossy().as_ref());
+
+        let mut favorite = history.is_favorite(&rom.path);
+        if ui.checkbox(im_str!("##favorite"), &mut favorite) {
+            history.toggle_favorite(&rom.path);
+        }
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Favorite");
+        }
+        ui.same_line(0.0);
+
+        let mut label = rom.title.clone();
+        if let Some(variant) = &rom.variant {
+            label.push_str(&format!(" ({})", variant));
+        }
+        let credits: Vec<&str> = [rom.author.as_deref(), rom.year.as_deref()]
+            .iter()
+            .flatten()
+            .copied()
+            .collect();
+        if !credits.is_empty() {
+            label.push_str(&format!(" [{}]", credits.join(", ")));
+        }
+        if Selectable::new(&ImString::new(label)).build(ui) {
+            *selected = Some(rom.path.clone());
+        }
+
+        id.pop(ui);
+    }
+
+    // Entries in the favorites or recents whose file is not in the library, like files
+    // opened from elsewhere. They can still be started as long as the file exists.
+    fn render_external(ui: &Ui, path: &Path, selected: &mut Option<PathBuf>) {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if path.is_file() {
+            let id = ui.push_id(path.to_string_lossy().as_ref());
+            if Selectable::new(&ImString::new(name)).build(ui) {
+                *selected = Some(path.to_path_buf());
+            }
+            id.pop(ui);
+        } else {
+            ui.text_disabled(name);
+        }
+        if ui.is_item_hovered() {
+            ui.tooltip_text(format!("Not in the ROM library: {}", path.display()));
+        }
+    }
+}
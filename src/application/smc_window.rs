@@ -0,0 +1,62 @@
+use super::emu_thread::EmulatorThread;
+use super::emu_window::RGBA;
+use crate::config::settings::Settings;
+use crate::emulator::chip8::Emulator;
+use imgui::*;
+
+/// Window listing the writes of the running ROM into its own code.
+pub struct SmcWindow {
+    pub open: bool,
+}
+
+impl SmcWindow {
+    pub fn new() -> SmcWindow {
+        SmcWindow { open: false }
+    }
+
+    pub fn render(&mut self, ui: &Ui, emulator: &EmulatorThread, settings: &mut Settings) {
+        if !self.open {
+            return;
+        }
+        // Only locked while the window is open
+        let mut emulator = emulator.lock();
+        let emulator: &mut Emulator = &mut emulator;
+
+        let mut open = self.open;
+        imgui::Window::new(im_str!("Self-Modifying Code"))
+            .size([360.0, 260.0], Condition::FirstUseEver)
+            .position([420.0, 140.0], Condition::FirstUseEver)
+            .opened(&mut open)
+            .build(ui, || {
+                if ui.checkbox(im_str!("Pause on write"), &mut settings.pause_on_smc) {
+                    emulator.pause_on_smc = settings.pause_on_smc;
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("Pause right after the ROM writes into code it executed");
+                }
+                ui.same_line(0.0);
+                if ui.small_button(im_str!("Clear")) {
+                    emulator.smc_writes.clear();
+                }
+                ui.separator();
+
+                if emulator.smc_writes.is_empty() {
+                    ui.text_disabled("No writes into executed code so far.");
+                    return;
+                }
+                let color = ui.push_style_color(
+                    StyleColor::Text,
+                    RGBA::to_rgba_normalized([255, 180, 0, 255]),
+                );
+                // Newest first
+                for write in emulator.smc_writes.iter().rev() {
+                    ui.text(format!(
+                        "Cycle {}: {:04X} at {:03X} wrote into code at {:03X}",
+                        write.cycle, write.instruction, write.pc, write.address
+                    ));
+                }
+                color.pop(ui);
+            });
+        self.open = open;
+    }
+}
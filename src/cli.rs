@@ -0,0 +1,187 @@
+use crate::emulator::keyboard::KeyLayout;
+use crate::emulator::known::RomSettings;
+use crate::emulator::quirks::QuirkPreset;
+use clap::{value_t, App, Arg, ArgMatches, SubCommand};
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Settings given on the command line.
+#[derive(Clone)]
+pub struct Options {
+    pub rom: Option<PathBuf>,
+    pub rom_dirs: Vec<PathBuf>,
+    pub scale: Option<f32>,
+    pub settings: RomSettings, // take precedence over the recommended settings of a ROM
+    pub seed: Option<u64>,
+    pub paused: bool,
+    pub fullscreen: bool,
+    pub server: Option<u16>, // loopback port to accept JSON-RPC commands on
+    pub tui: bool,
+    pub software: bool,        // draw on the CPU instead of with wgpu
+    pub lint: Option<PathBuf>, // ROM to check instead of running anything
+}
+
+impl Options {
+    const MAX_SCALE: f32 = 64.0; // already larger than any screen
+    const MAX_CLOCK_SPEED: u32 = 100_000; // far beyond any real interpreter
+
+    pub fn from_args() -> Options {
+        let quirk_presets: Vec<&str> = QuirkPreset::ALL
+            .iter()
+            .map(|preset| preset.name())
+            .collect();
+        let key_layouts: Vec<&str> = KeyLayout::ALL.iter().map(|layout| layout.name()).collect();
+
+        let matches = App::new("chippus")
+            .version(env!("CARGO_PKG_VERSION"))
+            .about("Yet another CHIP-8 emulator")
+            .arg(
+                Arg::with_name("rom")
+                    .value_name("ROM")
+                    .help("ROM file to start right away"),
+            )
+            .arg(
+                Arg::with_name("rom-dir")
+                    .long("rom-dir")
+                    .value_name("DIR")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Directory to look for ROMs in, can be given more than once"),
+            )
+            .arg(
+                Arg::with_name("scale")
+                    .long("scale")
+                    .value_name("FACTOR")
+                    .validator(Options::in_range(1.0f32, Options::MAX_SCALE))
+                    .help("Size of a CHIP-8 pixel on screen, the window is sized to fit"),
+            )
+            .arg(
+                Arg::with_name("speed")
+                    .long("speed")
+                    .value_name("HZ")
+                    .validator(Options::in_range(1u32, Options::MAX_CLOCK_SPEED))
+                    .help("Instructions executed per second"),
+            )
+            .arg(
+                Arg::with_name("quirks")
+                    .long("quirks")
+                    .value_name("PRESET")
+                    .possible_values(&quirk_presets)
+                    .help("Interpreter behaviour to emulate"),
+            )
+            .arg(
+                Arg::with_name("seed")
+                    .long("seed")
+                    .value_name("SEED")
+                    .help("Seed for the random number generator, for reproducible runs"),
+            )
+            .arg(
+                Arg::with_name("keys")
+                    .long("keys")
+                    .value_name("LAYOUT")
+                    .possible_values(&key_layouts)
+                    .help("Keyboard layout of the hex keypad"),
+            )
+            .arg(
+                Arg::with_name("paused")
+                    .long("paused")
+                    .help("Load the ROM without starting it"),
+            )
+            .arg(
+                Arg::with_name("fullscreen")
+                    .long("fullscreen")
+                    .help("Start in fullscreen game mode, showing only the display"),
+            )
+            .arg(
+                Arg::with_name("server")
+                    .long("server")
+                    .value_name("PORT")
+                    .help("Accept JSON-RPC commands from scripts on this port of localhost"),
+            )
+            .arg(
+                Arg::with_name("tui")
+                    .long("tui")
+                    .requires("rom")
+                    .help("Run the ROM in the terminal instead of a window"),
+            )
+            .arg(
+                Arg::with_name("software")
+                    .long("software")
+                    .help("Use a plain window drawn without the GPU, as when no adapter is found"),
+            )
+            .subcommand(
+                SubCommand::with_name("lint")
+                    .about("Check a ROM for problems without running it")
+                    .arg(
+                        Arg::with_name("rom")
+                            .value_name("ROM")
+                            .required(true)
+                            .help("ROM file to check"),
+                    ),
+            )
+            .get_matches();
+
+        Options {
+            rom: matches.value_of("rom").map(PathBuf::from),
+            rom_dirs: matches
+                .values_of("rom-dir")
+                .map(|dirs| dirs.map(PathBuf::from).collect())
+                .unwrap_or_default(),
+            scale: Options::parse(&matches, "scale"),
+            settings: RomSettings {
+                quirks: Options::parse(&matches, "quirks"),
+                clock_speed: Options::parse(&matches, "speed"),
+                key_layout: Options::parse(&matches, "keys"),
+            },
+            seed: Options::parse(&matches, "seed"),
+            paused: matches.is_present("paused"),
+            fullscreen: matches.is_present("fullscreen"),
+            server: Options::parse(&matches, "server"),
+            tui: matches.is_present("tui"),
+            software: matches.is_present("software"),
+            lint: matches
+                .subcommand_matches("lint")
+                .and_then(|lint| lint.value_of("rom"))
+                .map(PathBuf::from),
+        }
+    }
+
+    // Checks that an argument is a number from `min` to `max`
+    fn in_range<T>(min: T, max: T) -> impl Fn(String) -> Result<(), String>
+    where
+        T: FromStr + PartialOrd + Display,
+    {
+        move |value| match value.parse::<T>() {
+            Ok(number) if number >= min && number <= max => Ok(()),
+            _ => Err(format!("must be a number from {} to {}", min, max)),
+        }
+    }
+
+    // Parses the value of an argument, exiting with a usage error if it is invalid
+    fn parse<T: FromStr>(matches: &ArgMatches, name: &str) -> Option<T> {
+        matches
+            .value_of(name)
+            .map(|_| value_t!(matches, name, T).unwrap_or_else(|e| e.exit()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_range_checks_bounds_and_numbers() {
+        let scale = Options::in_range(1.0f32, Options::MAX_SCALE);
+        assert!(scale("1".to_string()).is_ok());
+        assert!(scale("2.5".to_string()).is_ok());
+        assert!(scale("0".to_string()).is_err());
+        assert!(scale("-3".to_string()).is_err());
+        assert!(scale("wide".to_string()).is_err());
+
+        let speed = Options::in_range(1u32, Options::MAX_CLOCK_SPEED);
+        assert!(speed("700".to_string()).is_ok());
+        assert!(speed("0".to_string()).is_err());
+        assert!(speed("1.5".to_string()).is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use super::config_dir;
+use crate::emulator::cheats::Cheat;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Cheats of every ROM, kept in `cheats.toml` in the config directory by the ROM's SHA-1, so
+/// they follow the ROM when it is renamed or moved.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CheatFile {
+    roms: BTreeMap<String, Vec<Cheat>>,
+}
+
+impl CheatFile {
+    const FILE: &'static str = "cheats.toml";
+
+    fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(CheatFile::FILE))
+    }
+
+    /// Reads the cheats file, starting without cheats if it is missing or broken.
+    pub fn load() -> CheatFile {
+        let path = match CheatFile::path() {
+            Some(path) => path,
+            None => return CheatFile::default(),
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => return CheatFile::default(),
+        };
+
+        match toml::from_str(&contents) {
+            Ok(cheats) => cheats,
+            Err(e) => {
+                eprintln!("Failed to parse '{0}', [ERROR]: {1}", path.display(), e);
+                CheatFile::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let path = match CheatFile::path() {
+            Some(path) => path,
+            None => return,
+        };
+
+        let result = toml::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| {
+                fs::create_dir_all(path.parent().unwrap_or(&path))
+                    .and_then(|_| fs::write(&path, contents))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            eprintln!("Failed to save '{0}', [ERROR]: {1}", path.display(), e);
+        }
+    }
+
+    pub fn cheats(&self, hash: &str) -> &[Cheat] {
+        self.roms.get(hash).map_or(&[], Vec::as_slice)
+    }
+
+    /// Replaces the cheats of a ROM and writes the file.
+    pub fn set_cheats(&mut self, hash: &str, cheats: Vec<Cheat>) {
+        if cheats.is_empty() {
+            self.roms.remove(hash);
+        } else {
+            self.roms.insert(hash.to_string(), cheats);
+        }
+        self.save();
+    }
+}
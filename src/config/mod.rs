@@ -0,0 +1,48 @@
+pub mod cheats;
+pub mod settings;
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Directory holding our configuration files, e.g. `~/.config/chippus` on Linux.
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("chippus"))
+}
+
+/// Directory holding our data files, e.g. `~/.local/share/chippus` on Linux.
+pub fn data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("chippus"))
+}
+
+/// Contents of `config.toml` in the config directory.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub rom_dirs: Vec<PathBuf>,
+}
+
+impl Config {
+    const FILE: &'static str = "config.toml";
+
+    /// Reads the config file, falling back to the defaults if it is missing or broken.
+    pub fn load() -> Config {
+        let path = match config_dir() {
+            Some(dir) => dir.join(Config::FILE),
+            None => return Config::default(),
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => return Config::default(),
+        };
+
+        match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Failed to parse '{0}', [ERROR]: {1}", path.display(), e);
+                Config::default()
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use super::config_dir;
+use crate::application::display_filter::{DisplayFilter, FilterState};
+use crate::application::palette::Palette;
+use crate::emulator::chip8::Emulator;
+use crate::emulator::keyboard::KeyLayout;
+use crate::emulator::known::RomSettings;
+use crate::emulator::quirks::{QuirkPreset, Quirks};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// User preferences remembered between runs, kept in `settings.toml` in the config directory.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub palette: Palette,
+    pub display_filter: DisplayFilter,
+    pub decay: f32,            // brightness lost per frame with the persistence filter
+    pub window_size: [f32; 2], // size of the main window
+    pub integer_scaling: bool,
+    pub keep_aspect: bool,
+    pub clock_speed: u32,
+    pub quirks: QuirkPreset,
+    pub detect_quirks: bool, // use the quirks of the platform a ROM seems to be written for
+    pub keys: String,        // keyboard characters bound to the keys 0 to F
+    pub pause_on_smc: bool,  // pause when a ROM writes into code it executed
+    pub last_rom: Option<PathBuf>,
+    pub layout: String, // imgui window layout, in imgui's ini format
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            palette: Palette::default(),
+            display_filter: DisplayFilter::None,
+            decay: FilterState::DEFAULT_DECAY,
+            window_size: [1398.0, 632.0],
+            integer_scaling: true,
+            keep_aspect: true,
+            clock_speed: Emulator::DEFAULT_CLOCK_SPEED,
+            quirks: QuirkPreset::Default,
+            detect_quirks: false,
+            keys: KeyLayout::Sequential.bindings().iter().collect(),
+            pause_on_smc: false,
+            last_rom: None,
+            layout: String::new(),
+        }
+    }
+}
+
+impl Settings {
+    const FILE: &'static str = "settings.toml";
+
+    fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(Settings::FILE))
+    }
+
+    /// Reads the settings file, falling back to the defaults if it is missing or broken.
+    pub fn load() -> Settings {
+        let path = match Settings::path() {
+            Some(path) => path,
+            None => return Settings::default(),
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => return Settings::default(),
+        };
+
+        match toml::from_str::<Settings>(&contents) {
+            Ok(mut settings) => {
+                settings.decay = FilterState::valid_decay(settings.decay);
+                settings
+            }
+            Err(e) => {
+                eprintln!("Failed to parse '{0}', [ERROR]: {1}", path.display(), e);
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let path = match Settings::path() {
+            Some(path) => path,
+            None => return,
+        };
+
+        let result = toml::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| {
+                fs::create_dir_all(path.parent().unwrap_or(&path))
+                    .and_then(|_| fs::write(&path, contents))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            eprintln!("Failed to save '{0}', [ERROR]: {1}", path.display(), e);
+        }
+    }
+
+    /// Sets the emulator up to run a ROM with `rom`, using these settings for what it leaves out.
+    pub fn configure(&self, emulator: &mut Emulator, rom: RomSettings) {
+        emulator.quirks = Quirks::from_preset(rom.quirks.unwrap_or(self.quirks));
+        emulator.clock_speed = rom.clock_speed.unwrap_or(self.clock_speed);
+        emulator.pause_on_smc = self.pause_on_smc;
+        // Quirks given for the ROM are used over the detected ones
+        emulator.detect_quirks = self.detect_quirks && rom.quirks.is_none();
+        emulator.keyboard.set_bindings(
+            rom.key_layout
+                .map_or_else(|| self.bindings(), |layout| layout.bindings()),
+        );
+    }
+
+    /// Returns the key bindings, or the sequential layout if `keys` is not 16 characters long.
+    pub fn bindings(&self) -> [char; 16] {
+        let keys: Vec<char> = self.keys.chars().map(|c| c.to_ascii_uppercase()).collect();
+        let mut bindings = KeyLayout::Sequential.bindings();
+        if keys.len() == bindings.len() {
+            bindings.copy_from_slice(&keys);
+        }
+        bindings
+    }
+}
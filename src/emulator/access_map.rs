@@ -0,0 +1,52 @@
+/// Remembers how each byte of RAM was accessed: executed as code, read as sprite or data, or
+/// written. Like the profiler it is kept in an `Option` on the emulator and costs nothing
+/// while switched off.
+pub struct AccessMap {
+    kinds: Vec<u8>, // every kind of access each byte has seen, as EXECUTE | READ | WRITE bits
+    last: Vec<u64>, // frame each byte was last accessed in
+    frame: u64,     // frames counted since the map was switched on
+}
+
+impl AccessMap {
+    pub const EXECUTE: u8 = 1;
+    pub const READ: u8 = 2;
+    pub const WRITE: u8 = 4;
+
+    pub fn new(ram_size: usize) -> AccessMap {
+        AccessMap {
+            kinds: vec![0; ram_size],
+            last: vec![0; ram_size],
+            frame: 0,
+        }
+    }
+
+    /// Counts an access of `kind` to the `len` bytes starting at `address`.
+    pub fn record(&mut self, address: usize, len: usize, kind: u8) {
+        let end = (address + len).min(self.kinds.len());
+        for byte in address.min(end)..end {
+            self.kinds[byte] |= kind;
+            self.last[byte] = self.frame;
+        }
+    }
+
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn clear(&mut self) {
+        *self = AccessMap::new(self.kinds.len());
+    }
+
+    /// The kinds of access `address` has seen, 0 if it was never touched.
+    pub fn kinds(&self, address: usize) -> u8 {
+        self.kinds.get(address).copied().unwrap_or(0)
+    }
+
+    /// Frames since `address` was last accessed, or `None` if it never was.
+    pub fn age(&self, address: usize) -> Option<u64> {
+        match self.kinds(address) {
+            0 => None,
+            _ => Some(self.frame - self.last[address]),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+/// How a byte has to compare to its value at the last search step to stay a candidate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+    Value(u8), // equal to this value, whatever it was before
+}
+
+impl Comparison {
+    fn matches(&self, before: u8, now: u8) -> bool {
+        match self {
+            Comparison::Equal => now == before,
+            Comparison::Changed => now != before,
+            Comparison::Increased => now > before,
+            Comparison::Decreased => now < before,
+            Comparison::Value(value) => now == *value,
+        }
+    }
+}
+
+/// Finds where a game keeps a value by comparing RAM between snapshots, e.g. looking for the
+/// bytes that decreased after a life was lost.
+pub struct Search {
+    snapshot: Vec<u8>,    // RAM as it was at the last step
+    candidates: Vec<u16>, // addresses that matched every step so far
+}
+
+impl Search {
+    /// Starts a search with every address as a candidate.
+    pub fn new(ram: &[u8]) -> Search {
+        Search {
+            snapshot: ram.to_vec(),
+            candidates: (0..ram.len() as u16).collect(),
+        }
+    }
+
+    /// Keeps the candidates whose byte compares to the last snapshot as asked, then takes a
+    /// new snapshot to compare the next step against.
+    pub fn narrow(&mut self, ram: &[u8], comparison: Comparison) {
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|address| {
+            let address = *address as usize;
+            comparison.matches(snapshot[address], ram[address])
+        });
+        self.snapshot = ram.to_vec();
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    /// The value of `address` at the last step.
+    pub fn previous(&self, address: u16) -> u8 {
+        self.snapshot[address as usize]
+    }
+}
+
+/// A byte of RAM set to a value, e.g. to keep the lives from running out.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Cheat {
+    pub name: String,
+    pub address: u16,
+    pub value: u8,
+    pub freeze: bool,  // set the byte every frame instead of once
+    pub enabled: bool, // frozen right now, only used with `freeze`
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comparisons() {
+        let cases = [
+            (Comparison::Equal, [true, false, false]),
+            (Comparison::Changed, [false, true, true]),
+            (Comparison::Increased, [false, true, false]),
+            (Comparison::Decreased, [false, false, true]),
+            (Comparison::Value(5), [true, false, false]),
+            (Comparison::Value(6), [false, true, false]),
+        ];
+        for (comparison, expected) in cases.iter() {
+            let matches = [(5, 5), (5, 6), (5, 4)]
+                .iter()
+                .map(|(before, now)| comparison.matches(*before, *now))
+                .collect::<Vec<bool>>();
+            assert_eq!(matches, expected, "{:?}", comparison);
+        }
+    }
+
+    #[test]
+    fn narrowing_keeps_the_matching_addresses() {
+        let mut search = Search::new(&[3, 3, 3, 3]);
+        assert_eq!(search.candidates(), [0, 1, 2, 3]);
+
+        search.narrow(&[3, 2, 4, 2], Comparison::Changed);
+        assert_eq!(search.candidates(), [1, 2, 3]);
+        assert_eq!(search.previous(1), 2);
+
+        // Each step compares to the one before, not to the start
+        search.narrow(&[3, 1, 4, 2], Comparison::Decreased);
+        assert_eq!(search.candidates(), [1]);
+
+        search.narrow(&[3, 1, 4, 2], Comparison::Value(7));
+        assert!(search.candidates().is_empty());
+    }
+}
@@ -1,10 +1,15 @@
-use rand::prelude::ThreadRng;
-use rand::Rng;
-use std::fs;
-use std::path::PathBuf;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, Read};
 
+use crate::analysis::Analysis;
+use crate::emulator::access_map::AccessMap;
 use crate::emulator::keyboard::Keyboard;
-use crate::emulator::screen::Screen;
+use crate::emulator::platform::Detection;
+use crate::emulator::profiler::Profiler;
+use crate::emulator::quirks::Quirks;
+use crate::emulator::screen::{Row, Screen};
 
 pub struct Emulator {
     pub ram: [u8; 4096], // The actual Memory or RAM
@@ -14,16 +19,183 @@ pub struct Emulator {
     pub pc: u16,         // 16-bit program counter
     pub delay_timer: u8, // 8-bit delay timer
     pub sound_timer: u8, // 8-bit sound timer,
-    total_dt: f32,       // total delay timer value
+    total_dt: f32,       // time since the last timer tick
+    cycle_dt: f32,       // time not yet spent on executing instructions
 
-    pub screen: Screen,     // screen structure
-    pub keyboard: Keyboard, // keyboard structure
-    pub pause: bool,        // a way to pause emulator,
-    rom_len: usize,         // size of rom loaded into memory or length of code
-    rng: ThreadRng,
+    pub screen: Screen,                     // screen structure
+    pub keyboard: Keyboard,                 // keyboard structure
+    pub pause: bool,                        // a way to pause emulator,
+    pub quirks: Quirks,                     // interpreter specific behaviour
+    pub clock_speed: u32,                   // instructions executed per second
+    pub speed: f32,                         // multiplier for how fast emulated time passes
+    rom_len: usize,                         // size of rom loaded into memory or length of code
+    seed: Option<u64>,                      // fixed seed for reproducible runs, random if not set
+    rng: ChaCha12Rng, // its position is saved with the state, so loading one replays the numbers
+    pub breakpoints: BTreeSet<u16>, // addresses to pause at before executing them
+    stopped_at: Option<u16>, // breakpoint the emulator last paused at
+    pub frozen: BTreeMap<u16, u8>, // RAM addresses set to a value every frame, for cheats
+    pub profiler: Option<Box<Profiler>>, // counts executed instructions while switched on
+    pub access_map: Option<Box<AccessMap>>, // remembers how RAM was accessed while switched on
+    pub cycles: u64,  // instructions executed since the ROM was loaded
+    executed: [u64; 4096 / 64], // bit set for every byte that was executed as code
+    pub smc_writes: VecDeque<SmcWrite>, // latest writes into executed code, oldest first
+    pub pause_on_smc: bool,
+    pub detect_quirks: bool, // pick the quirks by what `detection` finds when loading a ROM
+    pub detection: Option<Detection>, // platform the loaded ROM seems to be written for, if detected
+}
+
+/// A write into bytes that were executed as code before, i.e. self-modifying code.
+#[derive(Clone, Copy, Debug)]
+pub struct SmcWrite {
+    pub cycle: u64, // instructions executed before the one that wrote
+    pub pc: u16,    // address of the instruction that wrote
+    pub instruction: u16,
+    pub address: u16, // first byte written that was executed before
+}
+
+/// A copy of the machine state, to go back to later with `Emulator::load_state`.
+#[derive(Clone)]
+pub struct Snapshot {
+    ram: [u8; 4096],
+    stack: Vec<u16>,
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+    total_dt: f32,
+    cycle_dt: f32,
+    rows: [Row; Screen::HEIGHT],
+    rom_len: usize,
+    rng_seed: [u8; 32],
+    rng_position: u128, // words of the random number stream used up
+}
+
+impl Snapshot {
+    const MAGIC: [u8; 4] = *b"C8S2";
+    pub const MAX_STACK: usize = 16; // deepest stack a state can be written with
+    /// Length of every state written by `to_bytes`.
+    pub const SIZE: usize = 4
+        + 4096
+        + 1
+        + 2 * Snapshot::MAX_STACK
+        + 16
+        + 2
+        + 2
+        + 1
+        + 1
+        + 4
+        + 4
+        + 8 * Screen::HEIGHT
+        + 2
+        + 32
+        + 16;
+
+    /// Writes the state in a fixed size little endian format, e.g. for a save state file.
+    /// Returns nothing if the stack is deeper than `MAX_STACK`.
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        if self.stack.len() > Snapshot::MAX_STACK {
+            return None;
+        }
+
+        let mut data = Vec::with_capacity(Snapshot::SIZE);
+        data.extend_from_slice(&Snapshot::MAGIC);
+        data.extend_from_slice(&self.ram);
+        data.push(self.stack.len() as u8);
+        for i in 0..Snapshot::MAX_STACK {
+            data.extend_from_slice(&self.stack.get(i).copied().unwrap_or(0).to_le_bytes());
+        }
+        data.extend_from_slice(&self.v);
+        data.extend_from_slice(&self.i.to_le_bytes());
+        data.extend_from_slice(&self.pc.to_le_bytes());
+        data.push(self.delay_timer);
+        data.push(self.sound_timer);
+        data.extend_from_slice(&self.total_dt.to_le_bytes());
+        data.extend_from_slice(&self.cycle_dt.to_le_bytes());
+        for row in self.rows.iter() {
+            data.extend_from_slice(&row.to_le_bytes());
+        }
+        data.extend_from_slice(&(self.rom_len as u16).to_le_bytes());
+        data.extend_from_slice(&self.rng_seed);
+        data.extend_from_slice(&self.rng_position.to_le_bytes());
+        Some(data)
+    }
+
+    pub fn from_bytes(mut data: &[u8]) -> io::Result<Snapshot> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        if data.len() != Snapshot::SIZE || data[..4] != Snapshot::MAGIC {
+            return Err(invalid("not a CHIP-8 state"));
+        }
+        data = &data[4..];
+
+        let ram = read_bytes(&mut data)?;
+        let [stack_len] = read_bytes(&mut data)?;
+        let mut stack = Vec::new();
+        for _ in 0..Snapshot::MAX_STACK {
+            stack.push(u16::from_le_bytes(read_bytes(&mut data)?));
+        }
+        if stack_len as usize > Snapshot::MAX_STACK {
+            return Err(invalid("stack too deep"));
+        }
+        stack.truncate(stack_len as usize);
+        let v = read_bytes(&mut data)?;
+        let i = u16::from_le_bytes(read_bytes(&mut data)?);
+        let pc = u16::from_le_bytes(read_bytes(&mut data)?);
+        let [delay_timer, sound_timer] = read_bytes(&mut data)?;
+        let total_dt = f32::from_le_bytes(read_bytes(&mut data)?);
+        let cycle_dt = f32::from_le_bytes(read_bytes(&mut data)?);
+        let mut rows = [0; Screen::HEIGHT];
+        for row in rows.iter_mut() {
+            *row = Row::from_le_bytes(read_bytes(&mut data)?);
+        }
+        let rom_len = u16::from_le_bytes(read_bytes(&mut data)?) as usize;
+        let rng_seed = read_bytes(&mut data)?;
+        let rng_position = u128::from_le_bytes(read_bytes(&mut data)?);
+
+        // Running from a state that points outside of memory would crash the emulator
+        if rom_len > Emulator::MAX_ROM_LEN {
+            return Err(invalid("ROM too large"));
+        }
+        if pc > 0xFFE || stack.iter().any(|address| *address > 0xFFE) {
+            return Err(invalid("program counter outside of memory"));
+        }
+        if i > 0xFFF {
+            return Err(invalid("index register outside of memory"));
+        }
+
+        Ok(Snapshot {
+            ram,
+            stack,
+            v,
+            i,
+            pc,
+            delay_timer,
+            sound_timer,
+            total_dt,
+            cycle_dt,
+            rows,
+            rom_len,
+            rng_seed,
+            rng_position,
+        })
+    }
+}
+
+// Reads the next `N` bytes of a state
+fn read_bytes<const N: usize>(data: &mut &[u8]) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    data.read_exact(&mut bytes)?;
+    Ok(bytes)
 }
 
 impl Emulator {
+    pub const DEFAULT_CLOCK_SPEED: u32 = 600;
+    pub const TIMER_PERIOD: f32 = 1.0 / 60.0; // the timers count down at 60 Hz
+    pub const MIN_SPEED: f32 = 0.25;
+    pub const MAX_SPEED: f32 = 16.0;
+    pub const MAX_SMC_WRITES: usize = 100; // writes into code kept in `smc_writes`
+    pub const MAX_ROM_LEN: usize = 4096 - 0x200; // RAM from where ROMs are loaded
+
     pub fn new() -> Emulator {
         let fonts = [
             0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -55,9 +227,25 @@ impl Emulator {
             screen: Screen::new(),
             keyboard: Keyboard::new(),
             pause: true,
+            quirks: Quirks::default(),
+            clock_speed: Emulator::DEFAULT_CLOCK_SPEED,
+            speed: 1.0,
             rom_len: 0,
             total_dt: 0.0f32,
-            rng: ThreadRng::default(),
+            cycle_dt: 0.0f32,
+            seed: None,
+            rng: ChaCha12Rng::from_entropy(),
+            breakpoints: BTreeSet::new(),
+            stopped_at: None,
+            frozen: BTreeMap::new(),
+            profiler: None,
+            access_map: None,
+            cycles: 0,
+            executed: [0; 4096 / 64],
+            smc_writes: VecDeque::new(),
+            pause_on_smc: false,
+            detect_quirks: false,
+            detection: None,
         };
 
         emu.ram[..fonts.len()].copy_from_slice(&fonts[..]);
@@ -65,8 +253,10 @@ impl Emulator {
         emu
     }
 
+    // Addresses past the end of RAM wrap around to its start, as on machines with 4 KB of it
     fn fetch_instruction(&self) -> u16 {
-        (self.ram[(self.pc as usize)] as u16) << 8 | (self.ram[(self.pc as usize) + 1] as u16)
+        let pc = self.pc as usize;
+        (self.ram[pc & 0xFFF] as u16) << 8 | (self.ram[(pc + 1) & 0xFFF] as u16)
     }
 
     fn execute_instruction(&mut self, instruction: u16) {
@@ -142,11 +332,26 @@ impl Emulator {
                     // set value of register Vy into Vx (LD Vx, Vy) (8xy0)
                     0x0 => self.v[nibbles.1 as usize] = self.v[nibbles.2 as usize],
                     // OR value of register Vy with Vx and set in Vx (OR Vx, Vy) (8xy1)
-                    0x1 => self.v[nibbles.1 as usize] |= self.v[nibbles.2 as usize],
+                    0x1 => {
+                        self.v[nibbles.1 as usize] |= self.v[nibbles.2 as usize];
+                        if self.quirks.vf_reset {
+                            self.v[0xF] = 0;
+                        }
+                    }
                     // AND value of register Vy with Vx and set in Vx (AND Vx, Vy) (8xy2)
-                    0x2 => self.v[nibbles.1 as usize] &= self.v[nibbles.1 as usize],
+                    0x2 => {
+                        self.v[nibbles.1 as usize] &= self.v[nibbles.2 as usize];
+                        if self.quirks.vf_reset {
+                            self.v[0xF] = 0;
+                        }
+                    }
                     // XOR value of register Vy with Vx and set in Vx (XOR Vx, Vy) (8xy3)
-                    0x3 => self.v[nibbles.1 as usize] ^= self.v[nibbles.2 as usize],
+                    0x3 => {
+                        self.v[nibbles.1 as usize] ^= self.v[nibbles.2 as usize];
+                        if self.quirks.vf_reset {
+                            self.v[0xF] = 0;
+                        }
+                    }
                     // ADD value of register Vy into Vx and set in Vx (ADD Vx, Vy) (8xy4)
                     0x4 => {
                         let x = nibbles.1 as usize;
@@ -168,8 +373,15 @@ impl Emulator {
                     // Shift right Vx by 1 (SHR Vx) {, Vy} (8xy6)
                     0x6 => {
                         let x = nibbles.1 as usize;
-                        self.v[0xF] = self.v[x] & 1;
+                        if x != nibbles.2 as usize {
+                            self.shifted_with_vy();
+                        }
+                        if self.quirks.shift_uses_vy {
+                            self.v[x] = self.v[nibbles.2 as usize];
+                        }
+                        let flag = self.v[x] & 1;
                         self.v[x] >>= 1;
+                        self.v[0xF] = flag;
                     }
                     // SUB value of register Vx from Vy and set in Vx (SUBN Vx, Vy) (8xy7)
                     0x7 => {
@@ -183,8 +395,15 @@ impl Emulator {
                     // Shift left Vx by 1 (SHL Vx) {, Vy} (8xyE)
                     0xE => {
                         let x = nibbles.1 as usize;
-                        self.v[0xF] = self.v[x] >> 7;
+                        if x != nibbles.2 as usize {
+                            self.shifted_with_vy();
+                        }
+                        if self.quirks.shift_uses_vy {
+                            self.v[x] = self.v[nibbles.2 as usize];
+                        }
+                        let flag = self.v[x] >> 7;
                         self.v[x] <<= 1;
+                        self.v[0xF] = flag;
                     }
                     _ => (),
                 }
@@ -203,7 +422,12 @@ impl Emulator {
 
             // jump to v0 + nnn (JP v0, addr) (Annn)
             (0xB, _, _, _) => {
-                self.pc = (instruction & 0x0FFF) + (self.v[0] as u16);
+                let register = if self.quirks.jump_uses_vx {
+                    nibbles.1 as usize
+                } else {
+                    0
+                };
+                self.pc = ((instruction & 0x0FFF) + (self.v[register] as u16)) & 0xFFF;
             }
 
             // random value AND kk and set value in Vx register (RNG Vx, byte) (Cxkk)
@@ -215,11 +439,17 @@ impl Emulator {
             (0xD, _, _, _) => {
                 let x_coord = self.v[nibbles.1 as usize] as usize;
                 let y_coord = self.v[nibbles.2 as usize] as usize;
+                let mut sprite = [0; 15];
+                let sprite = &mut sprite[..nibbles.3 as usize];
+                for (j, byte) in sprite.iter_mut().enumerate() {
+                    *byte = self.ram[(self.i as usize + j) & 0xFFF];
+                }
 
-                self.v[0xF] = self.screen.draw(
-                    (x_coord, y_coord),
-                    &self.ram[(self.i as usize)..((self.i + (nibbles.3 as u16)) as usize)],
-                ) as u8;
+                self.v[0xF] = self
+                    .screen
+                    .draw((x_coord, y_coord), sprite, self.quirks.clip_sprites)
+                    as u8;
+                self.record_access(self.i, nibbles.3 as usize, AccessMap::READ);
             }
 
             (0xE, _, _, _) => {
@@ -228,7 +458,7 @@ impl Emulator {
                     (0x9, 0xE) => {
                         if self
                             .keyboard
-                            .is_key_pressed(self.v[nibbles.1 as usize] as usize)
+                            .is_key_pressed(self.v[nibbles.1 as usize] as usize & 0xF)
                         {
                             self.pc += 2;
                         }
@@ -237,7 +467,7 @@ impl Emulator {
                     (0xA, 0x1) => {
                         if !self
                             .keyboard
-                            .is_key_pressed(self.v[nibbles.1 as usize] as usize)
+                            .is_key_pressed(self.v[nibbles.1 as usize] as usize & 0xF)
                         {
                             self.pc += 2;
                         }
@@ -269,7 +499,7 @@ impl Emulator {
 
                     // set i to i + Vx (ADD I, Vx) (Fx1E)
                     (0x1, 0xE) => {
-                        self.i += self.v[nibbles.1 as usize] as u16;
+                        self.i = self.i.wrapping_add(self.v[nibbles.1 as usize] as u16);
                     }
 
                     // set i equal to location of sprite = Vx value (ADD I, Vx) (Fx29)
@@ -280,26 +510,37 @@ impl Emulator {
                     // store BCD representation of Vx in memory locations I, I+1, and I+2 (LD B, Vx) (Fx33)
                     (0x3, 0x3) => {
                         let x = nibbles.1 as usize;
-                        self.ram[self.i as usize] = self.v[x] / 100;
-                        self.ram[(self.i as usize) + 1] = (self.v[x] / 10) % 10;
-                        self.ram[(self.i as usize) + 2] = self.v[x] % 10;
+                        let i = self.i as usize;
+                        self.ram[i & 0xFFF] = self.v[x] / 100;
+                        self.ram[(i + 1) & 0xFFF] = (self.v[x] / 10) % 10;
+                        self.ram[(i + 2) & 0xFFF] = self.v[x] % 10;
+                        self.record_access(self.i, 3, AccessMap::WRITE);
+                        self.check_smc(instruction, self.i, 3);
                     }
 
                     // store register V0 to Vx values in memory starting from location at reg (LD[I], Vx) (Fx55)
                     (0x5, 0x5) => {
                         let x = nibbles.1 as u16;
-                        self.ram[(self.i as usize)..((self.i + x + 1) as usize)]
-                            .copy_from_slice(&self.v[0..((x + 1) as usize)]);
-                        self.i += x + 1;
+                        for (offset, value) in self.v[..=x as usize].iter().enumerate() {
+                            self.ram[(self.i as usize + offset) & 0xFFF] = *value;
+                        }
+                        self.record_access(self.i, x as usize + 1, AccessMap::WRITE);
+                        self.check_smc(instruction, self.i, x as usize + 1);
+                        if self.quirks.load_store_increments_i {
+                            self.i = self.i.wrapping_add(x + 1);
+                        }
                     }
 
-                    // store register V0 to Vx equal to values in memory starting from location at I (LD Vx, [I]) (Fx56)
-                    (0x5, 0x6) => {
+                    // store register V0 to Vx equal to values in memory starting from location at I (LD Vx, [I]) (Fx65)
+                    (0x6, 0x5) => {
                         let x = nibbles.1 as u16;
-                        self.v[0..((x + 1) as usize)].copy_from_slice(
-                            &self.ram[(self.i as usize)..((self.i + x + 1) as usize)],
-                        );
-                        self.i += x + 1;
+                        for (offset, value) in self.v[..=x as usize].iter_mut().enumerate() {
+                            *value = self.ram[(self.i as usize + offset) & 0xFFF];
+                        }
+                        self.record_access(self.i, x as usize + 1, AccessMap::READ);
+                        if self.quirks.load_store_increments_i {
+                            self.i = self.i.wrapping_add(x + 1);
+                        }
                     }
                     _ => (),
                 }
@@ -314,55 +555,486 @@ impl Emulator {
         }
     }
 
+    /// Runs the emulator for `dt` seconds of real time, sped up or slowed down by `speed`.
     pub fn execute_cycle(&mut self, dt: f32) {
         if !self.pause {
-            self.update_timer(dt);
-            // fetch instruction from memory
-            let instruction = self.fetch_instruction();
+            self.run_for(dt * self.speed);
+        }
+    }
+
+    /// Runs until the timers tick next, i.e. to the start of the next 60 Hz frame.
+    pub fn advance_frame(&mut self) {
+        self.run_for(Emulator::TIMER_PERIOD - self.total_dt);
+    }
 
-            // decode and execute instruction
-            self.execute_instruction(instruction);
+    // Runs for `time` seconds of emulated time, ticking the timers in between instructions
+    fn run_for(&mut self, mut time: f32) {
+        while time > 0.0 {
+            let until_tick = Emulator::TIMER_PERIOD - self.total_dt;
+            if time < until_tick {
+                self.run_instructions(time);
+                self.total_dt += time;
+                return;
+            }
+
+            if !self.run_instructions(until_tick) {
+                return;
+            }
+            time -= until_tick;
+            self.total_dt = 0.0;
+            self.update_timers();
+            self.write_frozen();
+            if let Some(access_map) = &mut self.access_map {
+                access_map.next_frame();
+            }
         }
     }
 
-    pub fn load_rom(&mut self, romfile: &PathBuf) {
-        // Reset emulator
-        *self = Self::new();
+    // run as many instructions as the clock speed allows for the elapsed time, returns false if
+    // the emulator paused at a breakpoint
+    fn run_instructions(&mut self, time: f32) -> bool {
+        self.cycle_dt += time * self.clock_speed as f32;
+        while self.cycle_dt >= 1.0 {
+            if self.breakpoints.contains(&self.pc) && self.stopped_at != Some(self.pc) {
+                // Resuming runs the instruction at the breakpoint instead of stopping again
+                self.stopped_at = Some(self.pc);
+                self.pause = true;
+                self.cycle_dt = 0.0;
+                return false;
+            }
+            self.stopped_at = None;
+            self.cycle_dt -= 1.0;
+            self.step();
+            if self.pause {
+                // The instruction paused the emulator, e.g. by writing into code
+                self.cycle_dt = 0.0;
+                return false;
+            }
+        }
+        true
+    }
 
-        // Load ROM from file
-        let contents = match fs::read(romfile) {
-            Err(e) => {
-                println!(
-                    "Failed to read file: '{0}', [ERROR]: {1}",
-                    romfile.display(),
-                    e
-                );
-                std::process::exit(0)
+    pub fn step(&mut self) {
+        // A skip at the end of RAM goes past it
+        self.pc &= 0xFFF;
+        // fetch instruction from memory
+        let instruction = self.fetch_instruction();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(self.pc, instruction);
+        }
+        self.record_access(self.pc, 2, AccessMap::EXECUTE);
+        for address in [self.pc, (self.pc + 1) & 0xFFF].iter() {
+            if let Some(bits) = self.executed.get_mut(*address as usize / 64) {
+                *bits |= 1 << (address % 64);
             }
-            Ok(f) => f,
-        };
+        }
+
+        // decode and execute instruction
+        self.execute_instruction(instruction);
+        self.cycles += 1;
+    }
+
+    /// Resets the machine and loads the contents of a ROM file into memory. The file is read by
+    /// the caller, so the core does not need a file system, e.g. in the browser.
+    pub fn load_rom(&mut self, contents: &[u8]) -> io::Result<()> {
+        self.reset_to_rom(contents)?;
+        // Analyzing the ROM takes a while, so it is only done when the quirks depend on it
+        if self.detect_quirks {
+            self.detect(contents, &Analysis::new(contents));
+        }
+        Ok(())
+    }
+
+    /// Loads a ROM like `load_rom`, and always detects its platform with an analysis the caller
+    /// already made of it.
+    pub fn load_analyzed_rom(&mut self, contents: &[u8], analysis: &Analysis) -> io::Result<()> {
+        self.reset_to_rom(contents)?;
+        self.detect(contents, analysis);
+        Ok(())
+    }
+
+    fn reset_to_rom(&mut self, contents: &[u8]) -> io::Result<()> {
+        Emulator::check_rom(contents)?;
+
+        // Reset emulator, keeping the user's settings
+        let quirks = self.quirks;
+        let clock_speed = self.clock_speed;
+        let speed = self.speed;
+        let bindings = self.keyboard.bindings();
+        let seed = self.seed;
+        let pause_on_smc = self.pause_on_smc;
+        let detect_quirks = self.detect_quirks;
+        let breakpoints = std::mem::take(&mut self.breakpoints);
+        let mut profiler = self.profiler.take();
+        let mut access_map = self.access_map.take();
+        *self = Self::new();
+        self.breakpoints = breakpoints;
+        // A new ROM starts a new profile and access map
+        if let Some(profiler) = &mut profiler {
+            profiler.clear();
+        }
+        if let Some(access_map) = &mut access_map {
+            access_map.clear();
+        }
+        self.profiler = profiler;
+        self.access_map = access_map;
+        self.quirks = quirks;
+        self.clock_speed = clock_speed;
+        self.speed = speed;
+        self.pause_on_smc = pause_on_smc;
+        self.detect_quirks = detect_quirks;
+        self.keyboard.set_bindings(bindings);
+        self.set_seed(seed);
 
         // Copy rom in memory
-        self.ram[(0x200 as usize)..(0x200 + contents.len() as usize)]
-            .copy_from_slice(&contents[..]);
+        self.ram[0x200..0x200 + contents.len()].copy_from_slice(contents);
         self.rom_len = contents.len();
 
         self.pause = false;
-        self.rng = rand::thread_rng();
+        Ok(())
+    }
+
+    // Lets the detection see an 8xy6/8xyE with x != y, and takes the quirks it picks from that
+    fn shifted_with_vy(&mut self) {
+        let preset = self.detection.as_mut().and_then(Detection::shift_with_vy);
+        if let (true, Some(preset)) = (self.detect_quirks, preset) {
+            self.quirks = Quirks::from_preset(preset);
+        }
+    }
+
+    fn detect(&mut self, contents: &[u8], analysis: &Analysis) {
+        let detection = Detection::from_analysis(contents, analysis);
+        if let (true, Some(preset)) = (self.detect_quirks, detection.preset) {
+            self.quirks = Quirks::from_preset(preset);
+        }
+        self.detection = Some(detection);
+    }
+
+    /// Fails for ROMs `load_rom` would not load, so a frontend can check before changing anything.
+    pub fn check_rom(contents: &[u8]) -> io::Result<()> {
+        if contents.len() > Emulator::MAX_ROM_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "ROM is {} bytes, but only {} bytes fit into memory",
+                    contents.len(),
+                    Emulator::MAX_ROM_LEN
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
+        self.rng = match seed {
+            Some(seed) => ChaCha12Rng::seed_from_u64(seed),
+            None => ChaCha12Rng::from_entropy(),
+        };
+    }
+
+    pub fn save_state(&self) -> Snapshot {
+        Snapshot {
+            ram: self.ram,
+            stack: self.stack.clone(),
+            v: self.v,
+            i: self.i,
+            pc: self.pc,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            total_dt: self.total_dt,
+            cycle_dt: self.cycle_dt,
+            rows: *self.screen.rows(),
+            rom_len: self.rom_len,
+            rng_seed: self.rng.get_seed(),
+            rng_position: self.rng.get_word_pos(),
+        }
+    }
+
+    /// Puts the machine back into a saved state. Settings such as the quirks are kept.
+    pub fn load_state(&mut self, snapshot: &Snapshot) {
+        let snapshot = snapshot.clone();
+        self.ram = snapshot.ram;
+        self.stack = snapshot.stack;
+        self.v = snapshot.v;
+        self.i = snapshot.i;
+        self.pc = snapshot.pc;
+        self.delay_timer = snapshot.delay_timer;
+        self.sound_timer = snapshot.sound_timer;
+        self.total_dt = snapshot.total_dt;
+        self.cycle_dt = snapshot.cycle_dt;
+        self.screen.set_rows(snapshot.rows);
+        self.rom_len = snapshot.rom_len;
+        self.rng = ChaCha12Rng::from_seed(snapshot.rng_seed);
+        self.rng.set_word_pos(snapshot.rng_position);
+        self.stopped_at = None;
     }
 
     pub fn code_memory_location(&self) -> (usize, usize) {
         (0x200, 0x200 + self.rom_len)
     }
 
-    fn update_timer(&mut self, dt: f32) {
-        if self.delay_timer > 0 {
-            self.total_dt += dt;
-            const TIMER_PERIOD: f32 = 1.0 / 60.0;
-            while self.total_dt > TIMER_PERIOD {
-                self.total_dt -= TIMER_PERIOD;
-                self.delay_timer -= 1;
+    fn update_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    fn record_access(&mut self, address: u16, len: usize, kind: u8) {
+        if let Some(access_map) = &mut self.access_map {
+            access_map.record(address as usize, len, kind);
+        }
+    }
+
+    // Logs a write of `len` bytes at `address` if it lands in code that was executed, and
+    // pauses if asked to. Called after the instruction moved the PC on.
+    fn check_smc(&mut self, instruction: u16, address: u16, len: usize) {
+        let executed = &self.executed;
+        let written = (address as usize..address as usize + len).find(|byte| {
+            executed
+                .get(byte / 64)
+                .map_or(false, |bits| bits & (1 << (byte % 64)) != 0)
+        });
+        let address = match written {
+            Some(address) => address as u16,
+            None => return,
+        };
+
+        if self.smc_writes.len() == Emulator::MAX_SMC_WRITES {
+            self.smc_writes.pop_front();
+        }
+        self.smc_writes.push_back(SmcWrite {
+            cycle: self.cycles,
+            pc: self.pc - 2,
+            instruction,
+            address,
+        });
+        if self.pause_on_smc {
+            self.pause = true;
+        }
+    }
+
+    fn write_frozen(&mut self) {
+        for (address, value) in self.frozen.iter() {
+            if let Some(byte) = self.ram.get_mut(*address as usize) {
+                *byte = *value;
             }
         }
     }
 }
+
+impl Default for Emulator {
+    fn default() -> Emulator {
+        Emulator::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::platform::Platform;
+    use crate::emulator::quirks::QuirkPreset;
+
+    // Loads `rom` and executes `steps` instructions of it
+    fn run(rom: &[u8], steps: usize) -> Emulator {
+        let mut emulator = Emulator::new();
+        emulator.set_seed(Some(1));
+        emulator.load_rom(rom).unwrap();
+        for _ in 0..steps {
+            emulator.step();
+        }
+        emulator
+    }
+
+    #[test]
+    fn and_uses_vy() {
+        // LD V0, 0x0F; LD V1, 0x3C; AND V0, V1
+        let emulator = run(&[0x60, 0x0F, 0x61, 0x3C, 0x80, 0x12], 3);
+        assert_eq!(emulator.v[0], 0x0C);
+    }
+
+    #[test]
+    fn rejects_roms_larger_than_memory() {
+        assert!(Emulator::check_rom(&[0; Emulator::MAX_ROM_LEN]).is_ok());
+        assert!(Emulator::check_rom(&[0; Emulator::MAX_ROM_LEN + 1]).is_err());
+
+        let mut emulator = run(&[0x60, 0x2A], 1);
+        assert!(emulator.load_rom(&[0; Emulator::MAX_ROM_LEN + 1]).is_err());
+        assert_eq!(emulator.v[0], 0x2A); // the running ROM is left alone
+    }
+
+    // RND V0, 0xFF; RND V1, 0xFF, then again from the start
+    const RANDOM: [u8; 6] = [0xC0, 0xFF, 0xC1, 0xFF, 0x12, 0x00];
+
+    #[test]
+    fn states_replay_the_same_random_numbers() {
+        let mut emulator = run(&RANDOM, 3);
+        let snapshot = emulator.save_state();
+        emulator.step();
+        emulator.step();
+        let after = emulator.v;
+
+        emulator.load_state(&snapshot);
+        emulator.step();
+        emulator.step();
+        assert_eq!(emulator.v, after);
+    }
+
+    #[test]
+    fn saving_a_state_keeps_the_random_numbers() {
+        let mut saved = run(&RANDOM, 3);
+        saved.save_state();
+        let mut unsaved = run(&RANDOM, 3);
+        for _ in 0..4 {
+            saved.step();
+            unsaved.step();
+        }
+        assert_eq!(saved.v, unsaved.v);
+    }
+
+    #[test]
+    fn states_survive_bytes() {
+        // CALL 0x204; JP 0x202; LD I, 0x123; RND V0, 0xFF; DRW V0, V0, 5
+        let emulator = run(
+            &[0x22, 0x04, 0x12, 0x02, 0xA1, 0x23, 0xC0, 0xFF, 0xD0, 0x05],
+            4,
+        );
+        let bytes = emulator.save_state().to_bytes().unwrap();
+        assert_eq!(bytes.len(), Snapshot::SIZE);
+        let mut copy = Emulator::new();
+        copy.load_state(&Snapshot::from_bytes(&bytes).unwrap());
+
+        assert_eq!(copy.save_state().to_bytes().unwrap(), bytes);
+        assert_eq!((copy.pc, copy.i, &copy.stack), (0x20A, 0x123, &vec![0x202]));
+        assert_eq!(copy.screen.rows(), emulator.screen.rows());
+    }
+
+    #[test]
+    fn states_pointing_outside_of_memory_are_rejected() {
+        let bytes = run(&RANDOM, 1).save_state().to_bytes().unwrap();
+        assert!(Snapshot::from_bytes(&bytes).is_ok());
+        assert!(Snapshot::from_bytes(&bytes[1..]).is_err());
+
+        // Offsets of the values in the format written by `to_bytes`
+        let stack = 4 + 4096;
+        let i = stack + 1 + 2 * Snapshot::MAX_STACK + 16;
+        let pc = i + 2;
+        let rom_len = pc + 2 + 2 + 8 + 8 * Screen::HEIGHT;
+        let patched = |offset: usize, value: &[u8]| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + value.len()].copy_from_slice(value);
+            Snapshot::from_bytes(&bytes)
+        };
+        assert!(patched(stack, &[17]).is_err());
+        assert!(patched(stack, &[1, 0x00, 0x10]).is_err());
+        assert!(patched(i, &0x1000u16.to_le_bytes()).is_err());
+        assert!(patched(pc, &0xFFFu16.to_le_bytes()).is_err());
+        assert!(patched(rom_len, &0xE01u16.to_le_bytes()).is_err());
+        assert!(patched(rom_len, &0xE00u16.to_le_bytes()).is_ok());
+    }
+
+    #[test]
+    fn memory_wraps_around_at_the_end() {
+        // LD I, 0xFFF; DRW V0, V0, 2 draws the last byte of RAM and then the first, the top
+        // of the font's 0
+        let emulator = run(&[0xAF, 0xFF, 0xD0, 0x02], 2);
+        assert_eq!(emulator.screen.rows()[1] >> 56, 0xF0);
+
+        // LD I, 0xFFE; LD V0, 123; LD B, V0 writes the last digit to the start of RAM
+        let emulator = run(&[0xAF, 0xFE, 0x60, 0x7B, 0xF0, 0x33], 3);
+        assert_eq!(
+            (emulator.ram[0xFFE], emulator.ram[0xFFF], emulator.ram[0]),
+            (1, 2, 3)
+        );
+    }
+
+    #[test]
+    fn random_programs_do_not_panic() {
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        for program in 0..200 {
+            let mut rom = vec![0; Emulator::MAX_ROM_LEN];
+            rng.fill(&mut rom[..]);
+            let mut emulator = Emulator::new();
+            emulator.quirks.load_store_increments_i = program % 2 == 0;
+            emulator.quirks.jump_uses_vx = program % 3 == 0;
+            emulator.load_rom(&rom).unwrap();
+            emulator.keyboard.set(program % 16, true);
+            for _ in 0..2000 {
+                emulator.step();
+            }
+        }
+    }
+
+    #[test]
+    fn writes_into_executed_code_are_logged() {
+        // LD I, 0x200; LD V0, 0x12; LD [I], V0 overwrites the first instruction, then
+        // LD I, 0x20A; LD [I], V0 writes to bytes that never ran
+        let rom = [0xA2, 0x00, 0x60, 0x12, 0xF0, 0x55, 0xA2, 0x0A, 0xF0, 0x55];
+        let emulator = run(&rom, 5);
+        let writes: Vec<(u64, u16, u16, u16)> = emulator
+            .smc_writes
+            .iter()
+            .map(|write| (write.cycle, write.pc, write.instruction, write.address))
+            .collect();
+        assert_eq!(writes, vec![(2, 0x204, 0xF055, 0x200)]);
+        assert!(!emulator.pause);
+
+        let mut emulator = Emulator::new();
+        emulator.pause_on_smc = true;
+        emulator.load_rom(&rom).unwrap();
+        emulator.execute_cycle(1.0);
+        assert!(emulator.pause);
+        assert_eq!(emulator.pc, 0x206);
+    }
+
+    #[test]
+    fn smc_log_keeps_the_latest_writes() {
+        // LD V0, 0xA2; LD I, 0x202; LD [I], V0; JP 0x202 writes the byte that is already there
+        let rom = [0x60, 0xA2, 0xA2, 0x02, 0xF0, 0x55, 0x12, 0x02];
+        let emulator = run(&rom, 1 + 3 * 150);
+        let last = 2 + 3 * 149; // cycle of the last write
+        assert_eq!(emulator.smc_writes.len(), Emulator::MAX_SMC_WRITES);
+        assert_eq!(emulator.smc_writes.back().unwrap().cycle, last);
+        assert_eq!(emulator.smc_writes.front().unwrap().cycle, last - 3 * 99);
+    }
+
+    #[test]
+    fn platforms_are_only_detected_when_the_quirks_depend_on_it() {
+        // 00FF; JP 0x202
+        let rom = [0x00, 0xFF, 0x12, 0x02];
+        let mut emulator = Emulator::new();
+        emulator.load_rom(&rom).unwrap();
+        assert!(emulator.detection.is_none());
+
+        emulator.detect_quirks = true;
+        emulator.load_rom(&rom).unwrap();
+        assert_eq!(emulator.detection.unwrap().platform, Platform::SuperChip);
+        assert_eq!(emulator.quirks, Quirks::from_preset(QuirkPreset::SuperChip));
+
+        // An analysis made by the caller is used even without detecting quirks
+        let mut emulator = Emulator::new();
+        emulator
+            .load_analyzed_rom(&rom, &Analysis::new(&rom))
+            .unwrap();
+        assert_eq!(emulator.detection.unwrap().platform, Platform::SuperChip);
+        assert_eq!(emulator.quirks, Quirks::default());
+    }
+
+    #[test]
+    fn shifting_with_vy_switches_to_the_vip_quirks() {
+        // LD V1, 3; SHR V0, V1; JP 0x204
+        let rom = [0x61, 0x03, 0x80, 0x16, 0x12, 0x04];
+        let mut emulator = Emulator::new();
+        emulator.detect_quirks = true;
+        emulator.load_rom(&rom).unwrap();
+        emulator.step();
+        emulator.step();
+        assert_eq!(emulator.quirks, Quirks::from_preset(QuirkPreset::CosmacVip));
+        // The shift already ran with them
+        assert_eq!(emulator.v[0], 1);
+
+        // Without detection the shift keeps the quirks it has
+        let emulator = run(&rom, 2);
+        assert_eq!(emulator.quirks, Quirks::default());
+        assert_eq!(emulator.v[0], 0);
+    }
+}
@@ -1,12 +1,77 @@
-use winit::event::VirtualKeyCode;
+use std::fmt;
+use std::str::FromStr;
+
+/// Arrangement of the 16 CHIP-8 keys on a regular keyboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyLayout {
+    Sequential, // keys 0-F laid out in order over 1234/QWER/ASDF/ZXCV
+    Cosmac,     // the COSMAC VIP hex keypad (123C/456D/789E/A0BF) over the same keys
+}
+
+impl KeyLayout {
+    pub const ALL: [KeyLayout; 2] = [KeyLayout::Sequential, KeyLayout::Cosmac];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyLayout::Sequential => "sequential",
+            KeyLayout::Cosmac => "cosmac",
+        }
+    }
+
+    /// Returns the keyboard character bound to each CHIP-8 key, indexed by key value.
+    pub fn bindings(&self) -> [char; 16] {
+        match self {
+            KeyLayout::Sequential => [
+                '1', '2', '3', '4', 'Q', 'W', 'E', 'R', 'A', 'S', 'D', 'F', 'Z', 'X', 'C', 'V',
+            ],
+            KeyLayout::Cosmac => [
+                'X', '1', '2', '3', 'Q', 'W', 'E', 'A', 'S', 'D', 'Z', 'C', '4', 'R', 'F', 'V',
+            ],
+        }
+    }
+}
+
+impl fmt::Display for KeyLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for KeyLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        KeyLayout::ALL
+            .iter()
+            .find(|layout| layout.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("unknown key layout '{}'", s))
+    }
+}
+
+/// Returns the character typed by a key of the host keyboard, for the keys that can be bound
+/// to CHIP-8 keys. Takes the name winit and minifb give the key ("Key1", "Q", ...) or the
+/// character a terminal reports.
+pub fn key_char(name: &str) -> Option<char> {
+    let name = name.strip_prefix("Key").unwrap_or(name);
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Some(c.to_ascii_uppercase()),
+        _ => None,
+    }
+}
 
 pub struct Keyboard {
     keys: [bool; 16],
+    bindings: [char; 16],
 }
 
 impl Keyboard {
     pub fn new() -> Keyboard {
-        Keyboard { keys: [false; 16] }
+        Keyboard {
+            keys: [false; 16],
+            bindings: KeyLayout::Sequential.bindings(),
+        }
     }
 
     pub fn is_key_pressed(&self, key: usize) -> bool {
@@ -26,25 +91,40 @@ impl Keyboard {
         self.keys[key] = pressed;
     }
 
-    pub fn map_key(code: VirtualKeyCode) -> usize {
-        match code {
-            VirtualKeyCode::Key1 => 0,
-            VirtualKeyCode::Key2 => 1,
-            VirtualKeyCode::Key3 => 2,
-            VirtualKeyCode::Key4 => 3,
-            VirtualKeyCode::Q => 4,
-            VirtualKeyCode::W => 5,
-            VirtualKeyCode::E => 6,
-            VirtualKeyCode::R => 7,
-            VirtualKeyCode::A => 8,
-            VirtualKeyCode::S => 9,
-            VirtualKeyCode::D => 10,
-            VirtualKeyCode::F => 11,
-            VirtualKeyCode::Z => 12,
-            VirtualKeyCode::X => 13,
-            VirtualKeyCode::C => 14,
-            VirtualKeyCode::V => 15,
-            _ => return usize::default(),
-        }
+    pub fn bindings(&self) -> [char; 16] {
+        self.bindings
+    }
+
+    pub fn set_bindings(&mut self, bindings: [char; 16]) {
+        self.bindings = bindings;
+        self.keys = [false; 16];
+    }
+
+    /// Maps a keyboard character to the CHIP-8 key bound to it.
+    pub fn map_key(&self, key: char) -> Option<usize> {
+        let key = key.to_ascii_uppercase();
+        self.bindings.iter().position(|binding| *binding == key)
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Keyboard {
+        Keyboard::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_named_by_their_character() {
+        assert_eq!(key_char("Key7"), Some('7'));
+        assert_eq!(key_char("Q"), Some('Q'));
+        assert_eq!(key_char("q"), Some('Q'));
+        assert_eq!(key_char("F1"), None);
+        assert_eq!(key_char("NumPad1"), None);
+        assert_eq!(key_char("Space"), None);
+        assert_eq!(key_char(" "), None);
     }
 }
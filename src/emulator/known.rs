@@ -0,0 +1,102 @@
+use crate::emulator::keyboard::KeyLayout;
+use crate::emulator::quirks::QuirkPreset;
+use sha1::Sha1;
+
+/// Settings that are known to work best for a particular ROM.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RomSettings {
+    pub quirks: Option<QuirkPreset>,
+    pub clock_speed: Option<u32>,
+    pub key_layout: Option<KeyLayout>,
+}
+
+impl RomSettings {
+    /// Fills in the settings missing here with the ones from `other`.
+    pub fn or(self, other: RomSettings) -> RomSettings {
+        RomSettings {
+            quirks: self.quirks.or(other.quirks),
+            clock_speed: self.clock_speed.or(other.clock_speed),
+            key_layout: self.key_layout.or(other.key_layout),
+        }
+    }
+}
+
+// SHA-1 of the ROM contents paired with its recommended settings
+const KNOWN_ROMS: [(&str, RomSettings); 7] = [
+    // Blinky [Hans Christian Egeberg, 1991]
+    (
+        "d40abc54374e4343639f993e897e00904ddf85d9",
+        RomSettings {
+            quirks: Some(QuirkPreset::SuperChip),
+            clock_speed: Some(1000),
+            key_layout: None,
+        },
+    ),
+    // Blinky [Hans Christian Egeberg] (alt)
+    (
+        "f4169141735d8d60e51409ca7e73f4adedcefef2",
+        RomSettings {
+            quirks: Some(QuirkPreset::SuperChip),
+            clock_speed: Some(1000),
+            key_layout: None,
+        },
+    ),
+    // Space Invaders [David Winter]
+    (
+        "5c28a5f85289c9d859f95fd5eadbdcb1c30bb08b",
+        RomSettings {
+            quirks: Some(QuirkPreset::SuperChip),
+            clock_speed: None,
+            key_layout: None,
+        },
+    ),
+    // Space Invaders [David Winter] (alt)
+    (
+        "f100197f0f2f05b4f3c8c31ab9c2c3930d3e9571",
+        RomSettings {
+            quirks: Some(QuirkPreset::SuperChip),
+            clock_speed: None,
+            key_layout: None,
+        },
+    ),
+    // Animal Race [Brian Astle]
+    (
+        "a27dcf88a931f70c3ccf3c01a5410b263bac48bc",
+        RomSettings {
+            quirks: Some(QuirkPreset::CosmacVip),
+            clock_speed: None,
+            key_layout: Some(KeyLayout::Cosmac),
+        },
+    ),
+    // Clock Program [Bill Fisher, 1981]
+    (
+        "016345d75eef34448840845a9590d41e6bfdf46a",
+        RomSettings {
+            quirks: Some(QuirkPreset::CosmacVip),
+            clock_speed: None,
+            key_layout: None,
+        },
+    ),
+    // Keypad Test [Hap, 2006]
+    (
+        "0ebc4b92c6059d6193565644fb00108161d03d23",
+        RomSettings {
+            quirks: None,
+            clock_speed: None,
+            key_layout: Some(KeyLayout::Cosmac),
+        },
+    ),
+];
+
+/// The SHA-1 of ROM contents, as ROMs are known by.
+pub fn hash(contents: &[u8]) -> String {
+    Sha1::from(contents).digest().to_string()
+}
+
+/// Looks up the recommended settings for the ROM with the given content hash.
+pub fn recommended_settings(hash: &str) -> Option<RomSettings> {
+    KNOWN_ROMS
+        .iter()
+        .find(|(known, _)| *known == hash)
+        .map(|(_, settings)| *settings)
+}
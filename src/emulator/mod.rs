@@ -1,3 +1,9 @@
+pub mod access_map;
+pub mod cheats;
 pub mod chip8;
 pub mod keyboard;
+pub mod known;
+pub mod platform;
+pub mod profiler;
+pub mod quirks;
 pub mod screen;
@@ -0,0 +1,294 @@
+use crate::analysis::Analysis;
+use crate::emulator::known;
+use crate::emulator::quirks::QuirkPreset;
+use std::fmt;
+
+/// The machines and interpreters ROMs are written for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    HiresChip8, // the 64x64 mode of the COSMAC VIP
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::HiresChip8 => "Hires CHIP-8",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP",
+        }
+    }
+
+    /// Quirks ROMs for the platform expect, `None` if they usually run fine with any.
+    pub fn preset(&self) -> Option<QuirkPreset> {
+        match self {
+            Platform::Chip8 => None,
+            Platform::HiresChip8 => Some(QuirkPreset::CosmacVip),
+            Platform::SuperChip => Some(QuirkPreset::SuperChip),
+            Platform::XoChip => Some(QuirkPreset::XoChip),
+        }
+    }
+
+    fn from_preset(preset: QuirkPreset) -> Platform {
+        match preset {
+            QuirkPreset::Default | QuirkPreset::CosmacVip => Platform::Chip8,
+            QuirkPreset::SuperChip => Platform::SuperChip,
+            QuirkPreset::XoChip => Platform::XoChip,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// A guess of the platform a ROM was written for, from its opcodes and how it behaves.
+#[derive(Clone, Debug)]
+pub struct Detection {
+    pub platform: Platform,
+    pub preset: Option<QuirkPreset>, // None to keep the configured quirks
+    pub confidence: f32,             // from 0 to 1
+    pub reasons: Vec<String>,        // the evidence, strongest first
+    known: bool,                     // the preset came from the database of known ROMs
+    shift_seen: bool,                // an 8xy6/8xyE with x != y was executed
+}
+
+// Tells whether an instruction is an opcode only some platforms have, with the platform and a
+// description
+type Signature = (fn(u16) -> bool, Platform, &'static str);
+
+const SIGNATURES: [Signature; 10] = [
+    (|i| i == 0xF000, Platform::XoChip, "F000 long I load"),
+    (
+        |i| i & 0xF00F == 0x5002 || i & 0xF00F == 0x5003,
+        Platform::XoChip,
+        "5xy2/5xy3 register range",
+    ),
+    (
+        |i| i & 0xF0FF == 0xF001,
+        Platform::XoChip,
+        "Fx01 bit planes",
+    ),
+    (|i| i == 0xF002, Platform::XoChip, "F002 audio pattern"),
+    (|i| i & 0xFFF0 == 0x00D0, Platform::XoChip, "00Dn scroll up"),
+    (
+        |i| i == 0x00FF || i == 0x00FE,
+        Platform::SuperChip,
+        "00FF/00FE hires",
+    ),
+    (
+        |i| matches!(i, 0x00FB..=0x00FD) || i & 0xFFF0 == 0x00C0,
+        Platform::SuperChip,
+        "00Cn/00FB/00FC scrolling or 00FD exit",
+    ),
+    (
+        |i| i & 0xF0FF == 0xF030,
+        Platform::SuperChip,
+        "Fx30 big font",
+    ),
+    (
+        |i| i & 0xF0FF == 0xF075 || i & 0xF0FF == 0xF085,
+        Platform::SuperChip,
+        "Fx75/Fx85 flag registers",
+    ),
+    (
+        |i| i & 0xF00F == 0xD000,
+        Platform::SuperChip,
+        "Dxy0 16x16 sprites",
+    ),
+];
+
+impl Detection {
+    /// Guesses the platform from the opcodes in the reachable code of a ROM, unless the ROM is
+    /// in the database of known ROMs.
+    pub fn detect(rom: &[u8]) -> Detection {
+        Detection::from_analysis(rom, &Analysis::new(rom))
+    }
+
+    /// Like `detect`, with an analysis of the ROM the caller already made.
+    pub fn from_analysis(rom: &[u8], analysis: &Analysis) -> Detection {
+        let mut detection = Detection::default();
+        if analysis.instructions.get(&0x200) == Some(&0x1260) {
+            detection.platform = Platform::HiresChip8;
+            detection.confidence = 0.9;
+            detection
+                .reasons
+                .push("Starts with 1260, the jump of the hires CHIP-8 loader".to_string());
+        }
+
+        let mut found = Vec::new(); // signatures in the code, each counted once
+        for instruction in analysis.instructions.values() {
+            for (index, (matches, _, _)) in SIGNATURES.iter().enumerate() {
+                if matches(*instruction) && !found.contains(&index) {
+                    found.push(index);
+                }
+            }
+        }
+        found.sort_unstable();
+        for platform in [Platform::XoChip, Platform::SuperChip].iter() {
+            let signatures: Vec<&str> = found
+                .iter()
+                .map(|index| &SIGNATURES[*index])
+                .filter(|(_, signature_platform, _)| signature_platform == platform)
+                .map(|(_, _, name)| *name)
+                .collect();
+            if signatures.is_empty() {
+                continue;
+            }
+            // XO-CHIP has all the SUPER-CHIP opcodes, so the first platform found wins and the
+            // opcodes of both count for it
+            if detection.platform == Platform::Chip8 {
+                detection.platform = *platform;
+                detection.confidence = (0.5 + 0.15 * found.len() as f32).min(0.95);
+            }
+            detection.reasons.push(format!(
+                "Uses {} opcodes: {}",
+                platform,
+                signatures.join(", ")
+            ));
+        }
+
+        if detection.platform == Platform::Chip8 {
+            detection.confidence = 0.6;
+            detection
+                .reasons
+                .push("Only uses CHIP-8 opcodes".to_string());
+        }
+        detection.preset = detection.platform.preset();
+        if let Some(preset) =
+            known::recommended_settings(&known::hash(rom)).and_then(|settings| settings.quirks)
+        {
+            detection.known(preset);
+        }
+        detection
+    }
+
+    // Takes the preset the database of known ROMs has for this one
+    fn known(&mut self, preset: QuirkPreset) {
+        if self.platform.preset() != Some(preset) {
+            self.platform = Platform::from_preset(preset);
+        }
+        self.preset = Some(preset);
+        self.confidence = 1.0;
+        self.known = true;
+        self.reasons.insert(
+            0,
+            format!("Known ROM, runs best with the {} quirks", preset.label()),
+        );
+    }
+
+    // Called when an 8xy6/8xyE with x != y runs, which only makes a difference on interpreters
+    // shifting Vy. A plain CHIP-8 ROM doing it is likely written for the COSMAC VIP, so the
+    // preset for it is returned when the guess changes to it.
+    pub(crate) fn shift_with_vy(&mut self) -> Option<QuirkPreset> {
+        // The other platforms already come with their shift quirk
+        if self.shift_seen || self.known || self.platform != Platform::Chip8 {
+            return None;
+        }
+        self.shift_seen = true;
+        self.preset = Some(QuirkPreset::CosmacVip);
+        self.reasons.push(
+            "Shifts with 8xy6/8xyE where x != y, which only makes a difference on the COSMAC VIP"
+                .to_string(),
+        );
+        self.preset
+    }
+}
+
+impl Default for Detection {
+    fn default() -> Detection {
+        Detection {
+            platform: Platform::Chip8,
+            preset: None,
+            confidence: 0.0,
+            reasons: Vec::new(),
+            known: false,
+            shift_seen: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_chip8_roms() {
+        // LD V0, 1; JP 0x202
+        let detection = Detection::detect(&[0x60, 0x01, 0x12, 0x02]);
+        assert_eq!(detection.platform, Platform::Chip8);
+        assert_eq!(detection.preset, None);
+        assert_eq!(detection.confidence, 0.6);
+        assert_eq!(detection.reasons, vec!["Only uses CHIP-8 opcodes"]);
+
+        // Opcodes in bytes that never run are data: JP 0x204; 00FF; JP 0x204
+        let detection = Detection::detect(&[0x12, 0x04, 0x00, 0xFF, 0x12, 0x04]);
+        assert_eq!(detection.platform, Platform::Chip8);
+    }
+
+    #[test]
+    fn signatures_of_other_platforms() {
+        // 00FF; Fx30; JP 0x204
+        let detection = Detection::detect(&[0x00, 0xFF, 0xF0, 0x30, 0x12, 0x04]);
+        assert_eq!(detection.platform, Platform::SuperChip);
+        assert_eq!(detection.preset, Some(QuirkPreset::SuperChip));
+        assert_eq!(detection.confidence, 0.8);
+        assert_eq!(
+            detection.reasons,
+            vec!["Uses SUPER-CHIP opcodes: 00FF/00FE hires, Fx30 big font"]
+        );
+
+        // 00FF; 5xy2, where the analysis stops
+        let detection = Detection::detect(&[0x00, 0xFF, 0x50, 0x12]);
+        assert_eq!(detection.platform, Platform::XoChip);
+        assert_eq!(detection.preset, Some(QuirkPreset::XoChip));
+        assert_eq!(
+            detection.reasons,
+            vec![
+                "Uses XO-CHIP opcodes: 5xy2/5xy3 register range",
+                "Uses SUPER-CHIP opcodes: 00FF/00FE hires"
+            ]
+        );
+    }
+
+    #[test]
+    fn hires_loader() {
+        let detection = Detection::detect(&[0x12, 0x60]);
+        assert_eq!(detection.platform, Platform::HiresChip8);
+        assert_eq!(detection.preset, Some(QuirkPreset::CosmacVip));
+        assert_eq!(detection.confidence, 0.9);
+    }
+
+    #[test]
+    fn known_roms_win_over_the_guess() {
+        let mut detection = Detection::detect(&[0x60, 0x01, 0x12, 0x02]);
+        detection.known(QuirkPreset::SuperChip);
+        assert_eq!(detection.platform, Platform::SuperChip);
+        assert_eq!(detection.preset, Some(QuirkPreset::SuperChip));
+        assert_eq!(detection.confidence, 1.0);
+        assert!(detection.reasons[0].starts_with("Known ROM"));
+
+        // Known ROMs keep their preset when they shift with Vy
+        assert_eq!(detection.shift_with_vy(), None);
+        assert_eq!(detection.preset, Some(QuirkPreset::SuperChip));
+    }
+
+    #[test]
+    fn shifting_with_vy_picks_the_cosmac_vip() {
+        let mut detection = Detection::detect(&[0x60, 0x01, 0x12, 0x02]);
+        assert_eq!(detection.shift_with_vy(), Some(QuirkPreset::CosmacVip));
+        assert_eq!(detection.preset, Some(QuirkPreset::CosmacVip));
+        // Only the first shift changes the guess
+        assert_eq!(detection.shift_with_vy(), None);
+
+        // SUPER-CHIP ROMs ignore Vy on purpose
+        let mut detection = Detection::detect(&[0x00, 0xFF, 0x12, 0x02]);
+        assert_eq!(detection.shift_with_vy(), None);
+        assert_eq!(detection.preset, Some(QuirkPreset::SuperChip));
+    }
+}
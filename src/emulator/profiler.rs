@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Counts where the emulator spends its instructions. It is kept in an `Option` on the
+/// emulator, so nothing is counted (or paid for) while it is switched off.
+pub struct Profiler {
+    hits: Vec<u64>,            // instructions executed at each address
+    classes: [u64; 16],        // instructions executed by their first nibble
+    calls: BTreeMap<u16, u64>, // times each subroutine was called with 2nnn
+    total: u64,
+}
+
+impl Profiler {
+    /// Names of the instruction classes, by their first nibble.
+    pub const CLASSES: [&'static str; 16] = [
+        "0nnn CLS/RET/SYS",
+        "1nnn JP",
+        "2nnn CALL",
+        "3xkk SE",
+        "4xkk SNE",
+        "5xy0 SE",
+        "6xkk LD",
+        "7xkk ADD",
+        "8xyn ALU",
+        "9xy0 SNE",
+        "Annn LD I",
+        "Bnnn JP V0",
+        "Cxkk RND",
+        "Dxyn DRW",
+        "Ex SKP/SKNP",
+        "Fx timers/keys/memory",
+    ];
+
+    pub fn new(ram_size: usize) -> Profiler {
+        Profiler {
+            hits: vec![0; ram_size],
+            classes: [0; 16],
+            calls: BTreeMap::new(),
+            total: 0,
+        }
+    }
+
+    /// Counts `instruction` being executed at `pc`.
+    pub fn record(&mut self, pc: u16, instruction: u16) {
+        if let Some(hits) = self.hits.get_mut(pc as usize) {
+            *hits += 1;
+        }
+        self.classes[(instruction >> 12) as usize] += 1;
+        if instruction >> 12 == 0x2 {
+            *self.calls.entry(instruction & 0x0FFF).or_insert(0) += 1;
+        }
+        self.total += 1;
+    }
+
+    pub fn clear(&mut self) {
+        *self = Profiler::new(self.hits.len());
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn hits(&self, address: usize) -> u64 {
+        self.hits.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn max_hits(&self) -> u64 {
+        self.hits.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Addresses that were executed at least once, with their hit count.
+    pub fn hot_spots(&self) -> Vec<(u16, u64)> {
+        self.hits
+            .iter()
+            .enumerate()
+            .filter(|(_, hits)| **hits > 0)
+            .map(|(address, hits)| (address as u16, *hits))
+            .collect()
+    }
+
+    pub fn classes(&self) -> &[u64; 16] {
+        &self.classes
+    }
+
+    /// Times the subroutine at `address` was called.
+    pub fn calls(&self, address: u16) -> u64 {
+        self.calls.get(&address).copied().unwrap_or(0)
+    }
+
+    /// The profile as CSV, one `kind,key,count` line per address, instruction class and called
+    /// subroutine, e.g. `address,0x2A4,1200`.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("kind,key,count\n");
+        for (address, hits) in self.hot_spots() {
+            let _ = writeln!(csv, "address,{:#05X},{}", address, hits);
+        }
+        for (class, hits) in self.classes.iter().enumerate() {
+            let _ = writeln!(csv, "class,{:X},{}", class, hits);
+        }
+        for (address, calls) in self.calls.iter() {
+            let _ = writeln!(csv, "call,{:#05X},{}", address, calls);
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_lists_addresses_classes_and_calls() {
+        let mut profiler = Profiler::new(4096);
+        profiler.record(0x200, 0x2208);
+        profiler.record(0x208, 0x00EE);
+        profiler.record(0x202, 0x2208);
+        profiler.record(0x208, 0x00EE);
+        profiler.record(0x4000, 0x1200); // past the end of RAM, only the class is counted
+
+        let mut expected = String::from("kind,key,count\n");
+        expected.push_str("address,0x200,1\naddress,0x202,1\naddress,0x208,2\n");
+        expected.push_str("class,0,2\nclass,1,1\nclass,2,2\n");
+        for class in 3..16 {
+            expected.push_str(&format!("class,{:X},0\n", class));
+        }
+        expected.push_str("call,0x208,2\n");
+        assert_eq!(profiler.to_csv(), expected);
+        assert_eq!(profiler.total(), 5);
+    }
+}
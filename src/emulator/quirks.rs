@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Named sets of quirks matching the behaviour of well known interpreters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum QuirkPreset {
+    #[serde(rename = "default")]
+    Default,
+    #[serde(rename = "vip")]
+    CosmacVip,
+    #[serde(rename = "schip")]
+    SuperChip,
+    #[serde(rename = "xochip")]
+    XoChip,
+}
+
+impl QuirkPreset {
+    pub const ALL: [QuirkPreset; 4] = [
+        QuirkPreset::Default,
+        QuirkPreset::CosmacVip,
+        QuirkPreset::SuperChip,
+        QuirkPreset::XoChip,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuirkPreset::Default => "default",
+            QuirkPreset::CosmacVip => "vip",
+            QuirkPreset::SuperChip => "schip",
+            QuirkPreset::XoChip => "xochip",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            QuirkPreset::Default => "Chippus (default)",
+            QuirkPreset::CosmacVip => "COSMAC VIP",
+            QuirkPreset::SuperChip => "SUPER-CHIP",
+            QuirkPreset::XoChip => "XO-CHIP",
+        }
+    }
+}
+
+impl fmt::Display for QuirkPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for QuirkPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        QuirkPreset::ALL
+            .iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("unknown quirk preset '{}'", s))
+    }
+}
+
+/// Behavioural differences between CHIP-8 interpreters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8xy6/8xyE shift Vy into Vx instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    /// Fx55/Fx65 advance I past the last register
+    pub load_store_increments_i: bool,
+    /// Bxnn jumps to xnn + Vx instead of nnn + V0
+    pub jump_uses_vx: bool,
+    /// 8xy1/8xy2/8xy3 reset VF to 0
+    pub vf_reset: bool,
+    /// Sprites are clipped at the screen edge instead of wrapping around
+    pub clip_sprites: bool,
+}
+
+impl Quirks {
+    pub fn from_preset(preset: QuirkPreset) -> Quirks {
+        match preset {
+            QuirkPreset::Default => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                vf_reset: false,
+                clip_sprites: false,
+            },
+            QuirkPreset::CosmacVip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                vf_reset: true,
+                clip_sprites: true,
+            },
+            QuirkPreset::SuperChip => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+            },
+            QuirkPreset::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                vf_reset: false,
+                clip_sprites: false,
+            },
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::from_preset(QuirkPreset::Default)
+    }
+}
@@ -1,61 +1,188 @@
+/// A row of packed pixels, the leftmost pixel in the most significant bit. Sprites wrap around
+/// within the bits of the row, so its type has exactly as many bits as the screen is wide.
+pub trait PixelRow: Copy {
+    const WIDTH: usize;
+
+    /// XORs a sprite byte into the row at pixel `x`, dropping the pixels past the edge when
+    /// clipping and wrapping them around otherwise. Returns `None` if no pixel of it lands on
+    /// the row, else whether a lit pixel was turned off.
+    fn draw(&mut self, byte: u8, x: usize, clip: bool) -> Option<bool>;
+}
+
+impl PixelRow for u64 {
+    const WIDTH: usize = 64;
+
+    fn draw(&mut self, byte: u8, x: usize, clip: bool) -> Option<bool> {
+        let sprite = (byte as u64) << (u64::WIDTH - 8);
+        let sprite = if clip {
+            sprite >> x
+        } else {
+            sprite.rotate_right(x as u32)
+        };
+        if sprite == 0 {
+            return None;
+        }
+        let collision = *self & sprite != 0;
+        *self ^= sprite;
+        Some(collision)
+    }
+}
+
+impl PixelRow for u128 {
+    const WIDTH: usize = 128;
+
+    fn draw(&mut self, byte: u8, x: usize, clip: bool) -> Option<bool> {
+        let sprite = (byte as u128) << (u128::WIDTH - 8);
+        let sprite = if clip {
+            sprite >> x
+        } else {
+            sprite.rotate_right(x as u32)
+        };
+        if sprite == 0 {
+            return None;
+        }
+        let collision = *self & sprite != 0;
+        *self ^= sprite;
+        Some(collision)
+    }
+}
+
+/// A row of the 64x32 screen.
+pub type Row = u64;
+/// A row of the 128x64 SUPER-CHIP hires screen. The screen has no hires mode yet, but sprites
+/// are drawn on these rows the same way.
+pub type HiresRow = u128;
+
+#[derive(Clone)]
 pub struct Screen {
-    pub buffer: [u8; Screen::WIDTH * Screen::HEIGHT],
-    dirty: bool,
+    rows: [Row; Screen::HEIGHT],
+    dirty_rows: u64, // bit n is set when row n changed since the last redraw
 }
 
 impl Screen {
     pub const WIDTH: usize = 64;
     pub const HEIGHT: usize = 32;
+    pub const ALL_ROWS: u64 = (1 << Screen::HEIGHT) - 1;
 
     pub fn new() -> Screen {
-        let screen = Screen {
-            buffer: [0u8; Screen::HEIGHT * Screen::WIDTH],
-            dirty: true,
-        };
-
-        screen
+        Screen {
+            rows: [0; Screen::HEIGHT],
+            dirty_rows: Screen::ALL_ROWS,
+        }
     }
 
     pub fn clear(&mut self) {
-        self.buffer = [0u8; Screen::HEIGHT * Screen::WIDTH];
+        self.rows = [0; Screen::HEIGHT];
         self.set_dirty(true);
     }
 
     pub fn get_pixel(&self, x: usize, y: usize) -> u8 {
-        self.buffer[y * Screen::WIDTH + x]
+        (self.rows[y] >> (Screen::WIDTH - 1 - x) & 1) as u8
     }
 
-    pub fn set_pixel(&mut self, x: usize, y: usize, value: bool) {
-        self.buffer[y * Screen::WIDTH + x] = value as u8;
-        self.dirty = true;
-    }
-
-    pub fn draw(&mut self, coords: (usize, usize), sprite_data: &[u8]) -> bool {
-        let rows = sprite_data.len();
+    pub fn draw(&mut self, coords: (usize, usize), sprite_data: &[u8], clip: bool) -> bool {
         let mut collision = false;
-        for j in 0..rows {
-            let row = sprite_data[j];
-            for i in 0..8 {
-                let new_value = row >> (7 - i) & 0x01;
-                if new_value == 1 {
-                    let x_new = (coords.0 + i) % Screen::WIDTH;
-                    let y_new = (coords.1 + j) % Screen::HEIGHT;
-                    let old_value = self.get_pixel(x_new, y_new);
-                    if old_value == 1 {
-                        collision = true;
-                    }
-                    self.set_pixel(x_new, y_new, (new_value == 1) ^ (old_value == 1));
-                }
+        let (x, y) = (coords.0 % Screen::WIDTH, coords.1 % Screen::HEIGHT);
+        for (j, byte) in sprite_data.iter().enumerate() {
+            if clip && y + j >= Screen::HEIGHT {
+                break;
+            }
+            let row = (y + j) % Screen::HEIGHT;
+            if let Some(erased) = self.rows[row].draw(*byte, x, clip) {
+                collision |= erased;
+                self.dirty_rows |= 1 << row;
             }
         }
         collision
     }
 
+    pub fn rows(&self) -> &[Row; Screen::HEIGHT] {
+        &self.rows
+    }
+
+    /// Replaces every row, e.g. when going back to a saved state.
+    pub fn set_rows(&mut self, rows: [Row; Screen::HEIGHT]) {
+        self.rows = rows;
+        self.set_dirty(true);
+    }
+
     pub fn is_dirty(&self) -> bool {
-        self.dirty
+        self.dirty_rows != 0
     }
 
+    /// Marks every row as changed, or none.
     pub fn set_dirty(&mut self, value: bool) {
-        self.dirty = value;
+        self.dirty_rows = if value { Screen::ALL_ROWS } else { 0 };
+    }
+
+    /// Returns the rows changed since the last call, as a bit mask with bit n for row n.
+    pub fn take_dirty_rows(&mut self) -> u64 {
+        std::mem::replace(&mut self.dirty_rows, 0)
+    }
+}
+
+impl Default for Screen {
+    fn default() -> Screen {
+        Screen::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprites_wrap_around_the_edges() {
+        let mut screen = Screen::new();
+        assert!(!screen.draw((60, 31), &[0xFF, 0x81], false));
+
+        assert_eq!(screen.rows()[31], 0xF000_0000_0000_000F);
+        assert_eq!(screen.rows()[0], 0x1000_0000_0000_0008);
+    }
+
+    #[test]
+    fn sprites_are_clipped_at_the_edges() {
+        let mut screen = Screen::new();
+        screen.draw((60, 31), &[0xFF, 0x81], true);
+
+        assert_eq!(screen.rows()[31], 0xF);
+        assert_eq!(screen.rows()[0], 0);
+    }
+
+    #[test]
+    fn coordinates_wrap_before_clipping() {
+        let mut screen = Screen::new();
+        screen.draw((64 + 2, 32 + 1), &[0x80], true);
+
+        assert_eq!(screen.get_pixel(2, 1), 1);
+    }
+
+    #[test]
+    fn rows_are_as_wide_as_the_screen() {
+        assert_eq!(Row::WIDTH, Screen::WIDTH);
+    }
+
+    #[test]
+    fn hires_rows_wrap_after_128_pixels() {
+        let mut row: HiresRow = 0;
+        assert_eq!(row.draw(0xFF, 124, false), Some(false));
+        assert_eq!(row, 0xF << 124 | 0xF);
+        assert_eq!(row.draw(0x80, 127, false), Some(true));
+
+        let mut row: HiresRow = 0;
+        assert_eq!(row.draw(0xFF, 124, true), Some(false));
+        assert_eq!(row, 0xF);
+        assert_eq!(row.draw(0x0F, 124, true), None);
+    }
+
+    #[test]
+    fn drawing_twice_erases_and_collides() {
+        let mut screen = Screen::new();
+        screen.take_dirty_rows();
+        assert!(!screen.draw((0, 3), &[0xA0], false));
+        assert!(screen.draw((0, 3), &[0xA0], false));
+
+        assert_eq!(screen.rows()[3], 0);
+        assert_eq!(screen.take_dirty_rows(), 1 << 3);
     }
 }
@@ -0,0 +1,296 @@
+use crate::emulator::chip8::Emulator;
+use crate::emulator::screen::{Row, Screen};
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// What an agent sees after each step: the raw screen bits, one `Row` per line.
+pub type Observation = [Row; Screen::HEIGHT];
+
+/// A value the game keeps in RAM, e.g. the score or the number of lives left.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RamValue {
+    pub address: u16,
+    #[serde(default = "RamValue::default_len")]
+    pub len: u16, // number of bytes the value takes up
+    #[serde(default)]
+    pub digits: bool, // one decimal digit per byte, the way FX33 stores numbers
+}
+
+impl RamValue {
+    fn default_len() -> u16 {
+        1
+    }
+
+    /// Reads the value, big endian if it takes up more than one byte.
+    pub fn read(&self, ram: &[u8]) -> u32 {
+        let base = if self.digits { 10 } else { 256 };
+        (0..self.len as usize)
+            .map(|offset| ram[(self.address as usize + offset) % ram.len()] as u32)
+            .fold(0, |value, byte| value.wrapping_mul(base).wrapping_add(byte))
+    }
+}
+
+/// Gives a reward whenever a value changes, e.g. +1 for each point scored.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RewardRule {
+    #[serde(flatten)]
+    pub value: RamValue,
+    #[serde(default = "RewardRule::default_scale")]
+    pub scale: f32, // reward per unit the value goes up, negative to punish
+}
+
+impl RewardRule {
+    fn default_scale() -> f32 {
+        1.0
+    }
+}
+
+/// Ends the episode once a value matches, e.g. when the lives drop to 0.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DoneRule {
+    #[serde(flatten)]
+    pub value: RamValue,
+    pub equals: Option<u32>,
+    pub at_least: Option<u32>,
+    pub at_most: Option<u32>,
+}
+
+impl DoneRule {
+    fn matches(&self, value: u32) -> bool {
+        self.equals == Some(value)
+            || self.at_least.map_or(false, |at_least| value >= at_least)
+            || self.at_most.map_or(false, |at_most| value <= at_most)
+    }
+}
+
+/// How rewards are given and when episodes end for one ROM. CHIP-8 games have no common place
+/// for the score, so the rules are written per ROM, e.g. in a TOML file next to it:
+/// ```toml
+/// max_frames = 18000
+///
+/// [[rewards]]
+/// address = 0x2F3
+///
+/// [[done]]
+/// address = 0x2F3
+/// at_least = 9
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Rules {
+    #[serde(default)]
+    pub rewards: Vec<RewardRule>,
+    #[serde(default)]
+    pub done: Vec<DoneRule>,
+    pub max_frames: Option<u64>, // ends episodes that take too long
+}
+
+/// A Gym-style environment running a ROM headless, as fast as the host allows.
+///
+/// Actions hold one of the 16 keys down for a step, or none for `Env::NOOP`. Each step runs
+/// `frame_skip` frames of 1/60 of a second with the same action, summing up the rewards.
+pub struct Env {
+    pub emulator: Emulator, // e.g. to set the quirks and clock speed before `reset`
+    pub rules: Rules,
+    pub frame_skip: u32,
+    rom: Vec<u8>,
+    rewards: Vec<u32>, // last value of each reward rule
+    frames: u64,       // frames run in this episode
+    done: bool,
+}
+
+impl Env {
+    pub const ACTIONS: usize = 17;
+    pub const NOOP: usize = 16;
+
+    pub fn new(rom: Vec<u8>, rules: Rules) -> io::Result<Env> {
+        let mut emulator = Emulator::new();
+        // Fail early if the ROM does not fit, `reset` loads it again
+        emulator.load_rom(&rom)?;
+        Ok(Env {
+            emulator,
+            rules,
+            frame_skip: 4,
+            rom,
+            rewards: Vec::new(),
+            frames: 0,
+            done: false,
+        })
+    }
+
+    /// Starts a new episode. Episodes with the same seed play out the same for the same actions.
+    pub fn reset(&mut self, seed: Option<u64>) -> Observation {
+        self.emulator.set_seed(seed);
+        // The size was checked when the environment was created
+        let _ = self.emulator.load_rom(&self.rom);
+        self.rewards = self
+            .rules
+            .rewards
+            .iter()
+            .map(|rule| rule.value.read(&self.emulator.ram))
+            .collect();
+        self.frames = 0;
+        self.done = false;
+        self.observation()
+    }
+
+    /// Runs one step with `action` and returns the new observation, the reward collected on
+    /// the way and whether the episode is over. Steps after the end do nothing. Actions from
+    /// `ACTIONS` on press no key, like `NOOP`.
+    pub fn step(&mut self, action: usize) -> (Observation, f32, bool) {
+        if self.done {
+            return (self.observation(), 0.0, true);
+        }
+
+        for key in 0..Env::NOOP {
+            self.emulator.keyboard.set(key, key == action);
+        }
+        let mut reward = 0.0;
+        for _ in 0..self.frame_skip.max(1) {
+            self.emulator.advance_frame();
+            self.frames += 1;
+            reward += self.collect_reward();
+            if self.is_done() {
+                self.done = true;
+                break;
+            }
+        }
+        (self.observation(), reward, self.done)
+    }
+
+    pub fn observation(&self) -> Observation {
+        *self.emulator.screen.rows()
+    }
+
+    /// Frames run since the last `reset`.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    fn collect_reward(&mut self) -> f32 {
+        let ram = &self.emulator.ram;
+        let mut reward = 0.0;
+        for (rule, last) in self.rules.rewards.iter().zip(self.rewards.iter_mut()) {
+            let value = rule.value.read(ram);
+            reward += (value as f32 - *last as f32) * rule.scale;
+            *last = value;
+        }
+        reward
+    }
+
+    fn is_done(&self) -> bool {
+        let ram = &self.emulator.ram;
+        self.rules
+            .done
+            .iter()
+            .any(|rule| rule.matches(rule.value.read(ram)))
+            || self
+                .rules
+                .max_frames
+                .map_or(false, |max| self.frames >= max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts V0 up about once a frame and stores it at 0x300, 0x301 is 1 while key 5 is held
+    const COUNTER: [u8; 26] = [
+        0xA3, 0x00, // LD I, 0x300
+        0x70, 0x01, // ADD V0, 1
+        0x61, 0x00, // LD V1, 0
+        0x62, 0x05, // LD V2, 5
+        0xE2, 0xA1, // SKNP V2
+        0x61, 0x01, // LD V1, 1
+        0xF1, 0x55, // LD [I], V1
+        0x63, 0x01, // LD V3, 1
+        0xF3, 0x15, // LD DT, V3
+        0xF3, 0x07, // LD V3, DT
+        0x33, 0x00, // SE V3, 0
+        0x12, 0x12, // JP 0x212
+        0x12, 0x00, // JP 0x200
+    ];
+    const COUNT: RamValue = RamValue {
+        address: 0x300,
+        len: 1,
+        digits: false,
+    };
+
+    fn env(rules: Rules) -> Env {
+        let mut env = Env::new(COUNTER.to_vec(), rules).unwrap();
+        env.frame_skip = 1;
+        env.reset(Some(1));
+        env
+    }
+
+    #[test]
+    fn rewards_follow_the_value() {
+        let mut env = env(Rules {
+            rewards: vec![RewardRule {
+                value: COUNT,
+                scale: 2.0,
+            }],
+            ..Rules::default()
+        });
+        let mut total = 0.0;
+        for _ in 0..10 {
+            let (_, reward, done) = env.step(Env::NOOP);
+            total += reward;
+            assert!(!done);
+        }
+        let count = env.emulator.ram[0x300];
+        assert!(count > 0);
+        assert_eq!(total, 2.0 * count as f32);
+    }
+
+    #[test]
+    fn episodes_end_when_a_value_matches() {
+        let mut env = env(Rules {
+            done: vec![DoneRule {
+                value: COUNT,
+                equals: None,
+                at_least: Some(3),
+                at_most: None,
+            }],
+            ..Rules::default()
+        });
+        let mut steps = 0;
+        while !env.step(Env::NOOP).2 {
+            steps += 1;
+            assert!(steps < 100);
+        }
+        assert_eq!(env.emulator.ram[0x300], 3);
+
+        let frames = env.frames();
+        assert_eq!(env.step(Env::NOOP).1, 0.0);
+        assert_eq!(env.frames(), frames); // steps after the end do nothing
+        env.reset(None);
+        assert!(!env.step(Env::NOOP).2);
+    }
+
+    #[test]
+    fn episodes_end_after_max_frames() {
+        let mut env = env(Rules {
+            max_frames: Some(5),
+            ..Rules::default()
+        });
+        env.frame_skip = 2;
+        let done: Vec<bool> = (0..3).map(|_| env.step(Env::NOOP).2).collect();
+        assert_eq!(done, vec![false, false, true]);
+        assert_eq!(env.frames(), 5);
+    }
+
+    #[test]
+    fn actions_hold_a_key() {
+        let mut env = env(Rules::default());
+        env.step(5);
+        env.step(5);
+        assert_eq!(env.emulator.ram[0x301], 1);
+
+        for action in [Env::NOOP, Env::ACTIONS, usize::MAX].iter() {
+            env.step(*action);
+            env.step(*action);
+            assert_eq!(env.emulator.ram[0x301], 0);
+        }
+    }
+}
@@ -0,0 +1,11 @@
+//! The CHIP-8 emulator core, shared by the desktop frontends of the `chippus` binary and the
+//! browser frontend built for wasm32, plus a headless environment to train agents on ROMs and a
+//! static analysis to lint them.
+
+pub mod analysis;
+pub mod emulator;
+pub mod env;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
@@ -0,0 +1,85 @@
+use crate::config;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Favorite and recently played ROMs, persisted between sessions.
+pub struct History {
+    favorites: Vec<PathBuf>,
+    recent: Vec<PathBuf>, // most recently played first
+}
+
+impl History {
+    const MAX_RECENT: usize = 10;
+    const FAVORITES_FILE: &'static str = "favorites.txt";
+    const RECENT_FILE: &'static str = "recent.txt";
+
+    pub fn load() -> History {
+        History {
+            favorites: History::read_list(History::FAVORITES_FILE),
+            recent: History::read_list(History::RECENT_FILE),
+        }
+    }
+
+    pub fn favorites(&self) -> &[PathBuf] {
+        &self.favorites
+    }
+
+    pub fn recent(&self) -> &[PathBuf] {
+        &self.recent
+    }
+
+    pub fn is_favorite(&self, path: &Path) -> bool {
+        self.favorites.iter().any(|favorite| favorite == path)
+    }
+
+    pub fn toggle_favorite(&mut self, path: &Path) {
+        if self.is_favorite(path) {
+            self.favorites.retain(|favorite| favorite != path);
+        } else {
+            self.favorites.push(path.to_path_buf());
+        }
+        History::write_list(History::FAVORITES_FILE, &self.favorites);
+    }
+
+    pub fn add_recent(&mut self, path: &Path) {
+        self.recent.retain(|recent| recent != path);
+        self.recent.insert(0, path.to_path_buf());
+        self.recent.truncate(History::MAX_RECENT);
+        History::write_list(History::RECENT_FILE, &self.recent);
+    }
+
+    // Lists are stored as one path per line
+    fn read_list(file_name: &str) -> Vec<PathBuf> {
+        let path = match config::config_dir() {
+            Some(dir) => dir.join(file_name),
+            None => return Vec::new(),
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(PathBuf::from)
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn write_list(file_name: &str, paths: &[PathBuf]) {
+        let result = config::config_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))
+            .and_then(|dir| {
+                fs::create_dir_all(&dir)?;
+                let contents: String = paths
+                    .iter()
+                    .map(|path| format!("{}\n", path.display()))
+                    .collect();
+                fs::write(dir.join(file_name), contents)
+            });
+
+        if let Err(e) = result {
+            eprintln!("Failed to save '{0}', [ERROR]: {1}", file_name, e);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+pub mod history;
+pub mod rom;
+
+use crate::config::{self, Config};
+use glob::{glob, Pattern};
+use rom::RomInfo;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// All ROMs found in the ROM search paths.
+pub struct RomLibrary {
+    dirs: Vec<PathBuf>,
+    roms: Vec<RomInfo>,
+    errors: Vec<String>, // entries that could not be read during the last scan
+}
+
+impl RomLibrary {
+    pub const EXTENSIONS: [&'static str; 4] = ["ch8", "c8", "sc8", "xo8"];
+    pub const PATH_VAR: &'static str = "CHIPPUS_ROM_PATH";
+
+    pub fn new(dirs: Vec<PathBuf>) -> RomLibrary {
+        let mut library = RomLibrary {
+            dirs,
+            roms: Vec::new(),
+            errors: Vec::new(),
+        };
+        library.rescan();
+        library
+    }
+
+    /// Collects the search paths from the command line, the `CHIPPUS_ROM_PATH` environment
+    /// variable and the config file, in that order. The default locations are only used
+    /// when none of them name a directory.
+    pub fn search_paths(cli_dirs: &[PathBuf], config: &Config) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = cli_dirs.to_vec();
+        if let Some(paths) = env::var_os(RomLibrary::PATH_VAR) {
+            dirs.extend(env::split_paths(&paths).filter(|dir| !dir.as_os_str().is_empty()));
+        }
+        dirs.extend(config.rom_dirs.iter().cloned());
+
+        if dirs.is_empty() {
+            dirs = RomLibrary::default_paths()
+                .into_iter()
+                .filter(|dir| dir.is_dir())
+                .collect();
+        }
+
+        let mut unique: Vec<PathBuf> = Vec::new();
+        for dir in dirs {
+            let dir = fs::canonicalize(&dir).unwrap_or(dir);
+            if !unique.contains(&dir) {
+                unique.push(dir);
+            }
+        }
+        unique
+    }
+
+    // The user's data directory, the working directory, and the ROMs shipped with the
+    // repository when running from `target/debug`.
+    fn default_paths() -> Vec<PathBuf> {
+        let mut dirs = Vec::new();
+        if let Some(dir) = config::data_dir() {
+            dirs.push(dir.join("roms"));
+        }
+        dirs.push(PathBuf::from("roms"));
+        if let Some(dir) = env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        {
+            dirs.push(dir.join("../../roms"));
+        }
+        dirs
+    }
+
+    pub fn is_rom(path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| {
+                RomLibrary::EXTENSIONS
+                    .iter()
+                    .any(|known| ext.eq_ignore_ascii_case(known))
+            })
+            .unwrap_or(false)
+    }
+
+    pub fn rescan(&mut self) {
+        self.roms.clear();
+        self.errors.clear();
+
+        for dir in &self.dirs {
+            if !dir.is_dir() {
+                self.errors
+                    .push(format!("'{}' is not a directory", dir.display()));
+                continue;
+            }
+
+            let pattern = format!("{}/**/*", Pattern::escape(&dir.to_string_lossy()));
+            let entries = match glob(&pattern) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    self.errors.push(format!("'{}': {}", dir.display(), e));
+                    continue;
+                }
+            };
+
+            for entry in entries {
+                match entry {
+                    Ok(path) if RomLibrary::is_rom(&path) && path.is_file() => {
+                        match RomInfo::from_path(&path) {
+                            Ok(rom) => self.roms.push(rom),
+                            Err(e) => self.errors.push(format!("'{}': {}", path.display(), e)),
+                        }
+                    }
+                    Ok(_) => (),
+                    Err(e) => self.errors.push(e.to_string()),
+                }
+            }
+        }
+    }
+
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    pub fn roms(&self) -> &[RomInfo] {
+        &self.roms
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub fn find(&self, path: &Path) -> Option<&RomInfo> {
+        self.roms.iter().find(|rom| rom.path == path)
+    }
+}
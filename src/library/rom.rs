@@ -0,0 +1,273 @@
+use crate::emulator::known::{self, RomSettings};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A ROM file together with the metadata we could find for it.
+#[derive(Clone)]
+pub struct RomInfo {
+    pub path: PathBuf,
+    pub category: String, // name of the directory the ROM is in, e.g. "games"
+    pub title: String,
+    pub author: Option<String>,
+    pub year: Option<String>,
+    pub variant: Option<String>, // e.g. "alt" for alternative versions of the same ROM
+    pub description: Option<String>,
+    pub hash: String, // SHA-1 of the ROM contents
+    pub recommended: Option<RomSettings>,
+}
+
+impl RomInfo {
+    pub fn from_path(path: &Path) -> io::Result<RomInfo> {
+        let contents = fs::read(path)?;
+        let hash = known::hash(&contents);
+
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let (title, author, year, variant) = RomInfo::parse_file_name(&stem);
+
+        let category = path
+            .parent()
+            .and_then(|parent| parent.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Ok(RomInfo {
+            description: RomInfo::read_description(path, &title),
+            path: path.to_path_buf(),
+            category,
+            title,
+            author,
+            year,
+            variant,
+            recommended: known::recommended_settings(&hash),
+            hash,
+        })
+    }
+
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Sentences of the description that talk about how the ROM is controlled.
+    pub fn controls(&self) -> Vec<String> {
+        let description = match &self.description {
+            Some(description) => description,
+            None => return Vec::new(),
+        };
+
+        description
+            .split(&['.', '!', '?'][..])
+            .map(|sentence| sentence.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|sentence| {
+                let lower = sentence.to_lowercase();
+                ["key", "press", "control", "use "]
+                    .iter()
+                    .any(|word| lower.contains(word))
+            })
+            .collect()
+    }
+
+    /// Splits a file name following the `Title [Author, Year] (variant)` convention
+    /// of the bundled ROMs into its parts.
+    fn parse_file_name(stem: &str) -> (String, Option<String>, Option<String>, Option<String>) {
+        let mut rest = stem.trim();
+
+        // Alternative versions are marked with "(alt)" before or after the credits
+        let mut variant = RomInfo::strip_variant(&mut rest);
+
+        // Author and year are in brackets, some files use parentheses instead
+        let mut credits = None;
+        for (open, close) in [('[', ']'), ('(', ')')].iter() {
+            if rest.ends_with(*close) {
+                if let Some(start) = rest.rfind(*open) {
+                    let inner = &rest[start + 1..rest.len() - 1];
+                    if *open == '[' || inner.contains(',') {
+                        credits = Some(inner.to_string());
+                        rest = rest[..start].trim_end();
+                        break;
+                    }
+                }
+            }
+        }
+
+        let mut author = None;
+        let mut year = None;
+        if let Some(credits) = credits {
+            let mut parts: Vec<&str> = credits.split(',').map(|part| part.trim()).collect();
+            if parts.len() > 1 && RomInfo::is_year(parts[parts.len() - 1]) {
+                year = parts.pop().map(|part| part.to_string());
+            }
+            let names = parts.join(", ");
+            if !names.is_empty() && names != "-" {
+                author = Some(names);
+            }
+        }
+
+        if variant.is_none() {
+            variant = RomInfo::strip_variant(&mut rest);
+        }
+
+        // Some titles carry the year instead, e.g. "Trip8 Demo (2008)"
+        let mut title = rest.to_string();
+        if year.is_none() && title.ends_with(')') {
+            if let Some(open) = title.rfind('(') {
+                if RomInfo::is_year(&title[open + 1..title.len() - 1]) {
+                    year = Some(title[open + 1..title.len() - 1].to_string());
+                    title = title[..open].trim_end().to_string();
+                }
+            }
+        }
+
+        (title, author, year, variant)
+    }
+
+    fn strip_variant(rest: &mut &str) -> Option<String> {
+        if !rest.ends_with(')') {
+            return None;
+        }
+        let open = rest.rfind('(')?;
+        let inner = rest[open + 1..rest.len() - 1].trim();
+        if !inner.to_lowercase().starts_with("alt") {
+            return None;
+        }
+        let variant = inner.to_string();
+        *rest = rest[..open].trim_end();
+        Some(variant)
+    }
+
+    // Years are four characters starting with "19" or "20", e.g. "1978" or "199x"
+    fn is_year(value: &str) -> bool {
+        value.len() == 4
+            && (value.starts_with("19") || value.starts_with("20"))
+            && value[2..].chars().all(|c| c.is_ascii_digit() || c == 'x')
+    }
+
+    /// Reads the `.txt` shipped next to the ROM. Alternative versions share the
+    /// description of the original, and some descriptions are named slightly differently
+    /// from their ROM, so fall back to any description with the same title.
+    fn read_description(path: &Path, title: &str) -> Option<String> {
+        let mut candidates = vec![path.with_extension("txt")];
+        if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+            if let Some(open) = stem.rfind(" (") {
+                candidates.push(path.with_file_name(format!("{}.txt", &stem[..open])));
+            }
+        }
+        if let Some(Ok(entries)) = path.parent().map(fs::read_dir) {
+            let prefixes = [format!("{} [", title), format!("{} (", title)];
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let candidate = entry.path();
+                if candidate.extension() != Some(OsStr::new("txt")) {
+                    continue;
+                }
+                if let Some(stem) = candidate.file_stem().and_then(|stem| stem.to_str()) {
+                    if prefixes.iter().any(|prefix| stem.starts_with(prefix)) {
+                        candidates.push(candidate.clone());
+                    }
+                }
+            }
+        }
+
+        candidates
+            .iter()
+            .filter_map(|candidate| fs::read(candidate).ok())
+            .map(|bytes| String::from_utf8_lossy(&bytes).trim().to_string())
+            .find(|text| !text.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(stem: &str) -> (String, Option<String>, Option<String>, Option<String>) {
+        RomInfo::parse_file_name(stem)
+    }
+
+    fn some(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn author_and_year_in_brackets() {
+        assert_eq!(
+            parts("Tetris [Fran Dachille, 1991]"),
+            (
+                "Tetris".to_string(),
+                some("Fran Dachille"),
+                some("1991"),
+                None
+            )
+        );
+        assert_eq!(
+            parts("Maze [David Winter, 199x]"),
+            ("Maze".to_string(), some("David Winter"), some("199x"), None)
+        );
+    }
+
+    #[test]
+    fn credits_in_parentheses_need_a_comma() {
+        assert_eq!(
+            parts("Lunar Lander (Udo Pernisz, 1979)"),
+            (
+                "Lunar Lander".to_string(),
+                some("Udo Pernisz"),
+                some("1979"),
+                None
+            )
+        );
+        assert_eq!(
+            parts("Pong (1 player)"),
+            ("Pong (1 player)".to_string(), None, None, None)
+        );
+    }
+
+    #[test]
+    fn variant_before_or_after_the_credits() {
+        assert_eq!(
+            parts("Blinky [Hans Christian Egeberg] (alt)"),
+            (
+                "Blinky".to_string(),
+                some("Hans Christian Egeberg"),
+                None,
+                some("alt")
+            )
+        );
+        assert_eq!(
+            parts("Maze (alt) [David Winter, 199x]"),
+            (
+                "Maze".to_string(),
+                some("David Winter"),
+                some("199x"),
+                some("alt")
+            )
+        );
+    }
+
+    #[test]
+    fn year_in_the_title() {
+        assert_eq!(
+            parts("Trip8 Demo (2008) [Revival Studios]"),
+            (
+                "Trip8 Demo".to_string(),
+                some("Revival Studios"),
+                some("2008"),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn plain_title() {
+        assert_eq!(
+            parts("  Airplane "),
+            ("Airplane".to_string(), None, None, None)
+        );
+    }
+}
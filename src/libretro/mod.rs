@@ -0,0 +1,603 @@
+//! A libretro core, to run the emulator in RetroArch and other libretro frontends. Built with
+//! `cargo build --release --lib --features libretro`.
+
+use crate::emulator::chip8::{Emulator, Snapshot};
+use crate::emulator::quirks::{QuirkPreset, Quirks};
+use crate::emulator::screen::Screen;
+use lazy_static::lazy_static;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+
+// Parts of libretro.h used by the core
+const API_VERSION: c_uint = 1;
+const DEVICE_JOYPAD: c_uint = 1;
+const MEMORY_SYSTEM_RAM: c_uint = 2;
+const REGION_NTSC: c_uint = 0;
+const ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+const ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const PIXEL_FORMAT_XRGB8888: c_uint = 1;
+
+type EnvironmentFn = unsafe extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+type VideoRefreshFn =
+    unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+type AudioSampleFn = unsafe extern "C" fn(left: i16, right: i16);
+type AudioSampleBatchFn = unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+type InputPollFn = unsafe extern "C" fn();
+type InputStateFn =
+    unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+#[repr(C)]
+pub struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct GameGeometry {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct SystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct SystemAvInfo {
+    geometry: GameGeometry,
+    timing: SystemTiming,
+}
+
+#[repr(C)]
+pub struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct Variable {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+struct Core {
+    emulator: Emulator,
+    rom: Vec<u8>,    // the loaded ROM, to start again on reset
+    frame: Vec<u32>, // XRGB8888 pixels handed to the frontend
+    audio: Vec<i16>, // interleaved stereo samples of one frame
+    phase: f32,      // position in the period of the beep, from 0 to 1
+    halted: bool,    // a frame panicked, so nothing runs until the game is reset or a state loaded
+}
+
+// `Mutex::new` is only const from Rust 1.63 on
+lazy_static! {
+    static ref CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+        environment: None,
+        video_refresh: None,
+        audio_sample_batch: None,
+        input_poll: None,
+        input_state: None,
+    });
+    static ref CORE: Mutex<Option<Core>> = Mutex::new(None);
+}
+
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: f64 = 44100.0;
+const BEEP_FREQUENCY: f32 = 440.0;
+const BEEP_VOLUME: i16 = 4000;
+const COLORS: [u32; 2] = [0x000000, 0xFFFFFF]; // pixels that are off and on
+
+// The hex keypad key of each joypad button, indexed by RETRO_DEVICE_ID_JOYPAD_*: B, Y, Select,
+// Start, Up, Down, Left, Right, A, X, L, R, L2, R2, L3, R3. The d-pad is on 2/8/4/6 and A on 5
+// as most games expect, the other buttons cover the rest of the keys.
+const JOYPAD_KEYS: [usize; 16] = [
+    0x0, 0x3, 0xC, 0xD, 0x2, 0x8, 0x4, 0x6, 0x5, 0x1, 0x7, 0x9, 0xA, 0xB, 0xE, 0xF,
+];
+
+// Core options shown by the frontend, the first value being the default
+const QUIRKS_OPTION: &[u8] = b"chippus_quirks\0";
+const CLOCK_OPTION: &[u8] = b"chippus_clock\0";
+const OPTIONS: [(&[u8], &[u8]); 2] = [
+    (QUIRKS_OPTION, b"Quirks; default|vip|schip|xochip\0"),
+    (
+        CLOCK_OPTION,
+        b"Clock speed (Hz); 600|700|800|1000|1200|1500|2000|100|200|300|400|500\0",
+    ),
+];
+
+// Runs the body of an entry point, returning `failed` if it panics, as unwinding into the C
+// code of the frontend is undefined behaviour
+fn guard<T>(failed: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(failed)
+}
+
+// A panic in one call should not keep the frontend from using the core afterwards
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    // Copied out, as the frontend may call back into the core
+    let callback = lock(&CALLBACKS).environment;
+    match callback {
+        Some(callback) => unsafe { callback(cmd, data) },
+        None => false,
+    }
+}
+
+// Returns the value the user picked for a core option
+fn option(key: &[u8]) -> Option<String> {
+    let mut variable = Variable {
+        key: key.as_ptr() as *const c_char,
+        value: ptr::null(),
+    };
+    let found = environment(
+        ENVIRONMENT_GET_VARIABLE,
+        &mut variable as *mut Variable as *mut c_void,
+    );
+    if !found || variable.value.is_null() {
+        return None;
+    }
+    let value = unsafe { CStr::from_ptr(variable.value) };
+    Some(value.to_string_lossy().into_owned())
+}
+
+impl Core {
+    fn new() -> Core {
+        Core {
+            emulator: Emulator::new(),
+            rom: Vec::new(),
+            frame: vec![0; Screen::WIDTH * Screen::HEIGHT],
+            audio: Vec::new(),
+            phase: 0.0,
+            halted: false,
+        }
+    }
+
+    fn apply_options(&mut self) {
+        if let Some(preset) = option(QUIRKS_OPTION).and_then(|value| value.parse().ok()) {
+            self.emulator.quirks = Quirks::from_preset(preset);
+        }
+        if let Some(clock_speed) = option(CLOCK_OPTION).and_then(|value| value.parse().ok()) {
+            self.emulator.clock_speed = clock_speed;
+        }
+    }
+
+    fn run_frame(&mut self, callbacks: &Callbacks) {
+        if let (Some(input_poll), Some(input_state)) = (callbacks.input_poll, callbacks.input_state)
+        {
+            unsafe { input_poll() };
+            for (id, key) in JOYPAD_KEYS.iter().enumerate() {
+                let pressed = unsafe { input_state(0, DEVICE_JOYPAD, 0, id as c_uint) } != 0;
+                self.emulator.keyboard.set(*key, pressed);
+            }
+        }
+
+        self.emulator.advance_frame();
+
+        if let Some(video_refresh) = callbacks.video_refresh {
+            for (i, pixel) in self.frame.iter_mut().enumerate() {
+                let value = self
+                    .emulator
+                    .screen
+                    .get_pixel(i % Screen::WIDTH, i / Screen::WIDTH);
+                *pixel = COLORS[value as usize];
+            }
+            let pitch = Screen::WIDTH * 4;
+            unsafe {
+                video_refresh(
+                    self.frame.as_ptr() as *const c_void,
+                    Screen::WIDTH as c_uint,
+                    Screen::HEIGHT as c_uint,
+                    pitch,
+                )
+            };
+        }
+
+        if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+            self.fill_audio();
+            unsafe { audio_sample_batch(self.audio.as_ptr(), self.audio.len() / 2) };
+        }
+    }
+
+    // A square wave while the sound timer runs, silence otherwise
+    fn fill_audio(&mut self) {
+        let frames = (SAMPLE_RATE / FPS) as usize;
+        let beeping = self.emulator.sound_timer > 0;
+        self.audio.clear();
+        for _ in 0..frames {
+            let sample = if !beeping {
+                0
+            } else if self.phase < 0.5 {
+                BEEP_VOLUME
+            } else {
+                -BEEP_VOLUME
+            };
+            self.audio.extend_from_slice(&[sample, sample]);
+            self.phase = (self.phase + BEEP_FREQUENCY / SAMPLE_RATE as f32) % 1.0;
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    guard((), || {
+        lock(&CALLBACKS).environment = Some(callback);
+
+        let mut variables: Vec<Variable> = OPTIONS
+            .iter()
+            .map(|(key, value)| Variable {
+                key: key.as_ptr() as *const c_char,
+                value: value.as_ptr() as *const c_char,
+            })
+            .collect();
+        variables.push(Variable {
+            key: ptr::null(),
+            value: ptr::null(),
+        });
+        environment(
+            ENVIRONMENT_SET_VARIABLES,
+            variables.as_mut_ptr() as *mut c_void,
+        );
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    guard((), || {
+        lock(&CALLBACKS).video_refresh = Some(callback);
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {
+    // The whole frame is sent at once with the batch callback
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    guard((), || {
+        lock(&CALLBACKS).audio_sample_batch = Some(callback);
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    guard((), || {
+        lock(&CALLBACKS).input_poll = Some(callback);
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    guard((), || {
+        lock(&CALLBACKS).input_state = Some(callback);
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {
+    guard((), || {
+        *lock(&CORE) = Some(Core::new());
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    guard((), || {
+        *lock(&CORE) = None;
+    })
+}
+
+/// # Safety
+/// `info` has to point to a `retro_system_info` to fill in.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    guard((), || {
+        *info = SystemInfo {
+            library_name: b"Chippus\0".as_ptr() as *const c_char,
+            library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+            valid_extensions: b"ch8|c8|sc8|xo8\0".as_ptr() as *const c_char,
+            need_fullpath: false,
+            block_extract: false,
+        };
+    })
+}
+
+/// # Safety
+/// `info` has to point to a `retro_system_av_info` to fill in.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    guard((), || {
+        *info = SystemAvInfo {
+            geometry: GameGeometry {
+                base_width: Screen::WIDTH as c_uint,
+                base_height: Screen::HEIGHT as c_uint,
+                max_width: Screen::WIDTH as c_uint,
+                max_height: Screen::HEIGHT as c_uint,
+                aspect_ratio: Screen::WIDTH as f32 / Screen::HEIGHT as f32,
+            },
+            timing: SystemTiming {
+                fps: FPS,
+                sample_rate: SAMPLE_RATE,
+            },
+        };
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    guard((), || {
+        if let Some(core) = lock(&CORE).as_mut() {
+            let rom = std::mem::take(&mut core.rom);
+            if core.emulator.load_rom(&rom).is_ok() {
+                core.rom = rom;
+                core.halted = false;
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let ran = guard(false, || {
+        let mut core = lock(&CORE);
+        let core = match core.as_mut() {
+            Some(core) if !core.halted => core,
+            _ => return true,
+        };
+
+        let mut updated = false;
+        let data = &mut updated as *mut bool as *mut c_void;
+        if environment(ENVIRONMENT_GET_VARIABLE_UPDATE, data) && updated {
+            core.apply_options();
+        }
+        let callbacks = *lock(&CALLBACKS);
+        core.run_frame(&callbacks);
+        true
+    });
+    if !ran {
+        // The panic may have left the emulator half way through an instruction
+        if let Some(core) = lock(&CORE).as_mut() {
+            core.halted = true;
+        }
+    }
+}
+
+/// # Safety
+/// `game` has to point to a `retro_game_info` with the contents of the ROM.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    guard(false, || {
+        if game.is_null() || (*game).data.is_null() {
+            return false;
+        }
+        let rom = std::slice::from_raw_parts((*game).data as *const u8, (*game).size).to_vec();
+
+        let mut format = PIXEL_FORMAT_XRGB8888;
+        if !environment(
+            ENVIRONMENT_SET_PIXEL_FORMAT,
+            &mut format as *mut c_uint as *mut c_void,
+        ) {
+            return false;
+        }
+
+        let mut core = lock(&CORE);
+        let core = core.get_or_insert_with(Core::new);
+        core.emulator.quirks = Quirks::from_preset(QuirkPreset::Default);
+        core.apply_options();
+        if core.emulator.load_rom(&rom).is_err() {
+            return false;
+        }
+        core.rom = rom;
+        core.halted = false;
+        true
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const GameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    guard((), || {
+        if let Some(core) = lock(&CORE).as_mut() {
+            *core = Core::new();
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    Snapshot::SIZE
+}
+
+/// # Safety
+/// `data` has to point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    guard(false, || {
+        let core = lock(&CORE);
+        let bytes = match core
+            .as_ref()
+            .and_then(|core| core.emulator.save_state().to_bytes())
+        {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+        if size < bytes.len() {
+            return false;
+        }
+        ptr::copy_nonoverlapping(bytes.as_ptr(), data as *mut u8, bytes.len());
+        true
+    })
+}
+
+/// # Safety
+/// `data` has to point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    guard(false, || {
+        let bytes = std::slice::from_raw_parts(data as *const u8, size);
+        let snapshot = match Snapshot::from_bytes(bytes) {
+            Ok(snapshot) => snapshot,
+            Err(_) => return false,
+        };
+        match lock(&CORE).as_mut() {
+            Some(core) => {
+                core.emulator.load_state(&snapshot);
+                core.halted = false;
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    guard(ptr::null_mut(), || match lock(&CORE).as_mut() {
+        Some(core) if id == MEMORY_SYSTEM_RAM => core.emulator.ram.as_mut_ptr() as *mut c_void,
+        _ => ptr::null_mut(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    guard(0, || match lock(&CORE).as_ref() {
+        Some(core) if id == MEMORY_SYSTEM_RAM => core.emulator.ram.len(),
+        _ => 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    lazy_static! {
+        // The core is global, so the tests take turns
+        static ref SERIAL: Mutex<()> = Mutex::new(());
+        static ref FRAME: Mutex<Vec<u32>> = Mutex::new(Vec::new()); // the last frame shown
+    }
+
+    // A frontend that only knows the pixel format
+    unsafe extern "C" fn frontend_environment(cmd: c_uint, _data: *mut c_void) -> bool {
+        cmd == ENVIRONMENT_SET_PIXEL_FORMAT
+    }
+
+    unsafe extern "C" fn frontend_video_refresh(
+        data: *const c_void,
+        _width: c_uint,
+        height: c_uint,
+        pitch: usize,
+    ) {
+        let pixels = std::slice::from_raw_parts(data as *const u32, pitch / 4 * height as usize);
+        *lock(&FRAME) = pixels.to_vec();
+    }
+
+    // Starts the core like a frontend does and loads `rom`
+    fn load_game(rom: &[u8]) -> bool {
+        retro_set_environment(frontend_environment);
+        retro_set_video_refresh(frontend_video_refresh);
+        retro_init();
+        let game = GameInfo {
+            path: ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: ptr::null(),
+        };
+        unsafe { retro_load_game(&game) }
+    }
+
+    // Runs `count` frames and returns what they showed
+    fn run_frames(count: usize) -> Vec<Vec<u32>> {
+        (0..count)
+            .map(|_| {
+                retro_run();
+                lock(&FRAME).clone()
+            })
+            .collect()
+    }
+
+    fn serialize() -> Vec<u8> {
+        let mut state = vec![0u8; retro_serialize_size()];
+        assert!(unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) });
+        state
+    }
+
+    fn unserialize(state: &[u8]) -> bool {
+        unsafe { retro_unserialize(state.as_ptr() as *const c_void, state.len()) }
+    }
+
+    #[test]
+    fn rewinding_replays_the_same_frames() {
+        let _serial = lock(&SERIAL);
+        // CLS; RND V0, 0x0F; LD F, V0; DRW V1, V1, 5; JP 0x200
+        let rom = [0x00, 0xE0, 0xC0, 0x0F, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x00];
+        assert!(load_game(&rom));
+        run_frames(1);
+        let state = serialize();
+        let frames = run_frames(3);
+        assert!(frames[2].contains(&COLORS[1]));
+
+        assert!(unserialize(&state));
+        assert_eq!(run_frames(3), frames);
+        assert!(!unserialize(&state[1..]));
+        retro_deinit();
+    }
+
+    #[test]
+    fn panics_fail_the_call() {
+        assert_eq!(guard(0, || panic!("in the core")), 0);
+        assert_eq!(guard(0, || 1), 1);
+    }
+}
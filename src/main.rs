@@ -1,12 +1,95 @@
+use analysis::Analysis;
 use application::app::Application;
+use chip8_emulator::{analysis, emulator};
 
 mod application;
-mod emulator;
+mod cli;
+mod config;
 mod imgui_wgpu_backend;
+mod library;
+mod server;
+mod software;
+mod tui;
 
+use std::path::Path;
 use std::rc::Rc;
 
 fn main() {
-    let app = Rc::new(Application::new());
-    app.run()
+    let options = cli::Options::from_args();
+    if let Some(path) = &options.lint {
+        let clean = lint(path);
+        std::process::exit(if clean { 0 } else { 1 });
+    }
+    if options.tui {
+        let ok = run_in_terminal(&options);
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+    if options.software || !Application::has_adapter() {
+        if !options.software {
+            eprintln!("No graphics adapter found, falling back to software rendering");
+        }
+        run_software(options);
+        return;
+    }
+    let app = Rc::new(Application::new(options.clone()));
+    if let Err(e) = app.run() {
+        eprintln!(
+            "Failed to render with wgpu, [ERROR]: {0}, falling back to software rendering",
+            e
+        );
+        run_software(options);
+    }
+}
+
+// Prints the problems the analysis finds in a ROM, returns false on errors
+fn lint(path: &Path) -> bool {
+    let rom = match std::fs::read(path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Failed to read ROM: '{0}', [ERROR]: {1}", path.display(), e);
+            return false;
+        }
+    };
+    let analysis = Analysis::new(&rom);
+    for problem in &analysis.problems {
+        println!(
+            "{}:{:03X}: {}: {}",
+            path.display(),
+            problem.address,
+            problem.severity,
+            problem.message
+        );
+    }
+    println!(
+        "{}: {} instructions in {} subroutines and the main program, {} bytes of data, {} problems",
+        path.display(),
+        analysis.instructions.len(),
+        analysis.subroutines.len(),
+        analysis.data_len(),
+        analysis.problems.len()
+    );
+    !analysis.has_errors()
+}
+
+// Runs the ROM given on the command line in the terminal, returns false if it could not
+fn run_in_terminal(options: &cli::Options) -> bool {
+    let path = match options.rom.as_deref() {
+        Some(path) => path,
+        None => {
+            eprintln!("Failed to run in the terminal, [ERROR]: --tui requires a ROM");
+            return false;
+        }
+    };
+    let result = tui::TerminalApp::new(options, path).and_then(|mut app| app.run());
+    if let Err(e) = result {
+        eprintln!("Failed to run ROM: '{0}', [ERROR]: {1}", path.display(), e);
+        return false;
+    }
+    true
+}
+
+fn run_software(options: cli::Options) {
+    if let Err(e) = software::SoftwareApp::new(options).run() {
+        eprintln!("Failed to open window, [ERROR]: {0}", e);
+    }
 }
@@ -0,0 +1,419 @@
+use crate::application::emu_thread;
+use crate::emulator::chip8::{Emulator, Snapshot};
+use crate::emulator::screen::Screen;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Lets scripts drive the emulator over a loopback TCP port. Each line sent is a JSON-RPC 2.0
+/// request and gets a response line back, e.g.
+/// `{"jsonrpc": "2.0", "method": "set_key", "params": {"key": 5, "pressed": true}, "id": 1}`.
+pub struct Server {
+    emulator: Arc<Mutex<Emulator>>,
+    states: Mutex<HashMap<String, Snapshot>>, // states saved by the clients, by slot name
+    roms: Mutex<Sender<RomRequest>>,          // where `load_rom` asks the application to load
+    load_timeout: Duration,                   // how long `load_rom` waits for the application
+}
+
+type RomReply = Sender<Result<(), String>>;
+
+/// A ROM a client wants loaded, with where to send whether that worked.
+pub struct RomRequest {
+    pub path: PathBuf,
+    pub paused: bool, // keep the ROM from running any instruction yet
+    reply: Arc<Mutex<Option<RomReply>>>, // taken by whoever is first: the loader or a timeout
+}
+
+impl RomRequest {
+    /// Takes the request on, returning where to reply, or `None` if the client gave up waiting
+    /// and the ROM must not be loaded.
+    pub fn accept(&self) -> Option<RomReply> {
+        self.reply.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    // Error codes from the JSON-RPC 2.0 specification
+    const PARSE_ERROR: i64 = -32700;
+    const METHOD_NOT_FOUND: i64 = -32601;
+    const INVALID_PARAMS: i64 = -32602;
+    const FAILED: i64 = -32000; // the method was called correctly, but could not be done
+
+    fn new(code: i64, message: String) -> RpcError {
+        RpcError { code, message }
+    }
+}
+
+impl Server {
+    // Most instructions `step` runs at once, as the emulator stays locked meanwhile
+    const MAX_STEPS: u32 = 100_000;
+    // How long `load_rom` waits for the application, which loads ROMs between UI frames
+    const LOAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Starts listening on `port` of the loopback interface, serving every client on its own
+    /// thread. ROMs are loaded by sending them to `roms`.
+    pub fn start(
+        port: u16,
+        emulator: Arc<Mutex<Emulator>>,
+        roms: Sender<RomRequest>,
+    ) -> io::Result<()> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let server = Arc::new(Server::new(emulator, roms));
+
+        thread::Builder::new()
+            .name("server".to_string())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let server = Arc::clone(&server);
+                    thread::spawn(move || server.serve(stream));
+                }
+            })?;
+        Ok(())
+    }
+
+    fn new(emulator: Arc<Mutex<Emulator>>, roms: Sender<RomRequest>) -> Server {
+        Server {
+            emulator,
+            states: Mutex::new(HashMap::new()),
+            roms: Mutex::new(roms),
+            load_timeout: Server::LOAD_TIMEOUT,
+        }
+    }
+
+    fn serve(&self, stream: TcpStream) {
+        let peer = stream
+            .peer_addr()
+            .map_or_else(|_| "unknown".to_string(), |addr| addr.to_string());
+        if let Err(e) = self.handle_client(stream) {
+            eprintln!("Failed to serve client: '{0}', [ERROR]: {1}", peer, e);
+        }
+    }
+
+    fn handle_client(&self, stream: TcpStream) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle_request(&line) {
+                writeln!(writer, "{}", response)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the response to send back, notifications (requests without an id) get none
+    fn handle_request(&self, line: &str) -> Option<Value> {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => {
+                let error = RpcError::new(RpcError::PARSE_ERROR, e.to_string());
+                return Some(Server::response(Value::Null, Err(error)));
+            }
+        };
+
+        let method = request.get("method").and_then(Value::as_str).unwrap_or("");
+        let params = request.get("params").unwrap_or(&Value::Null);
+        let result = self.call(method, params);
+        request
+            .get("id")
+            .map(|id| Server::response(id.clone(), result))
+    }
+
+    fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+        match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+            Err(error) => json!({
+                "jsonrpc": "2.0",
+                "error": { "code": error.code, "message": error.message },
+                "id": id,
+            }),
+        }
+    }
+
+    fn call(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        if method == "load_rom" {
+            return self.load_rom(params);
+        }
+
+        let mut emulator = emu_thread::lock(&self.emulator);
+        match method {
+            "pause" => {
+                emulator.pause = true;
+                Ok(Value::Null)
+            }
+            "resume" => {
+                emulator.pause = false;
+                Ok(Value::Null)
+            }
+            "step" => {
+                let count: u32 = param(params, "count", Some(1))?;
+                if count > Server::MAX_STEPS {
+                    let message = format!("count {} is over {}", count, Server::MAX_STEPS);
+                    return Err(RpcError::new(RpcError::INVALID_PARAMS, message));
+                }
+                for _ in 0..count {
+                    emulator.step();
+                }
+                Ok(Server::registers(&emulator))
+            }
+            "set_key" => {
+                let key: usize = param(params, "key", None)?;
+                if key > 0xF {
+                    let message = format!("key {} is not one of the keys 0-15", key);
+                    return Err(RpcError::new(RpcError::INVALID_PARAMS, message));
+                }
+                emulator
+                    .keyboard
+                    .set(key, param(params, "pressed", Some(true))?);
+                Ok(Value::Null)
+            }
+            "registers" => Ok(Server::registers(&emulator)),
+            "read_memory" => {
+                let address: usize = param(params, "address", None)?;
+                let length: usize = param(params, "length", Some(1))?;
+                match emulator.ram.get(address..address.saturating_add(length)) {
+                    Some(bytes) => Ok(json!(bytes)),
+                    None => {
+                        let message = format!(
+                            "{} bytes at {:#X} are past the end of memory",
+                            length, address
+                        );
+                        Err(RpcError::new(RpcError::INVALID_PARAMS, message))
+                    }
+                }
+            }
+            "screen" => {
+                // One string of '0' and '1' per row, the leftmost pixel first
+                let rows: Vec<String> = (0..Screen::HEIGHT)
+                    .map(|y| {
+                        (0..Screen::WIDTH)
+                            .map(|x| char::from(b'0' + emulator.screen.get_pixel(x, y)))
+                            .collect()
+                    })
+                    .collect();
+                Ok(json!({ "width": Screen::WIDTH, "height": Screen::HEIGHT, "rows": rows }))
+            }
+            "save_state" => {
+                let slot: String = param(params, "slot", Some("default".to_string()))?;
+                let mut states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+                states.insert(slot, emulator.save_state());
+                Ok(Value::Null)
+            }
+            "load_state" => {
+                let slot: String = param(params, "slot", Some("default".to_string()))?;
+                let states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+                match states.get(&slot) {
+                    Some(snapshot) => {
+                        emulator.load_state(snapshot);
+                        Ok(Value::Null)
+                    }
+                    None => {
+                        let message = format!("no state saved in slot '{}'", slot);
+                        Err(RpcError::new(RpcError::FAILED, message))
+                    }
+                }
+            }
+            "set_breakpoint" => {
+                emulator.breakpoints.insert(param(params, "address", None)?);
+                Ok(Value::Null)
+            }
+            "clear_breakpoint" => {
+                let address: u16 = param(params, "address", None)?;
+                Ok(json!(emulator.breakpoints.remove(&address)))
+            }
+            _ => {
+                let message = format!("unknown method '{}'", method);
+                Err(RpcError::new(RpcError::METHOD_NOT_FOUND, message))
+            }
+        }
+    }
+
+    // Has the application load the ROM, without holding the emulator while it does
+    fn load_rom(&self, params: &Value) -> Result<Value, RpcError> {
+        let path: PathBuf = param(params, "path", None)?;
+        let paused: bool = param(params, "paused", Some(false))?;
+        let failed = |message| Err(RpcError::new(RpcError::FAILED, message));
+
+        let (reply, result) = mpsc::channel();
+        let reply = Arc::new(Mutex::new(Some(reply)));
+        let request = RomRequest {
+            path,
+            paused,
+            reply: Arc::clone(&reply),
+        };
+        let sent = self
+            .roms
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .send(request);
+        if sent.is_err() {
+            return failed("the application does not load ROMs anymore".to_string());
+        }
+        let result = match result.recv_timeout(self.load_timeout) {
+            Ok(result) => result,
+            Err(_) => {
+                // Withdrawn, unless the application took it on meanwhile and is loading it
+                if reply
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .take()
+                    .is_some()
+                {
+                    return failed(
+                        "the application did not load the ROM in time, so it was not loaded"
+                            .to_string(),
+                    );
+                }
+                result
+                    .recv()
+                    .unwrap_or_else(|_| Err("the application did not load the ROM".to_string()))
+            }
+        };
+        match result {
+            Ok(()) => Ok(Value::Null),
+            Err(message) => failed(message),
+        }
+    }
+
+    fn registers(emulator: &Emulator) -> Value {
+        json!({
+            "pc": emulator.pc,
+            "i": emulator.i,
+            "v": emulator.v,
+            "stack": emulator.stack,
+            "delay_timer": emulator.delay_timer,
+            "sound_timer": emulator.sound_timer,
+            "paused": emulator.pause,
+        })
+    }
+}
+
+// Reads a named parameter, using `default` when the client left it out
+fn param<T: DeserializeOwned>(
+    params: &Value,
+    name: &str,
+    default: Option<T>,
+) -> Result<T, RpcError> {
+    match params.get(name) {
+        Some(value) => serde_json::from_value(value.clone()).map_err(|e| {
+            RpcError::new(
+                RpcError::INVALID_PARAMS,
+                format!("invalid parameter '{}': {}", name, e),
+            )
+        }),
+        None => default.ok_or_else(|| {
+            RpcError::new(
+                RpcError::INVALID_PARAMS,
+                format!("missing parameter '{}'", name),
+            )
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::mpsc::Receiver;
+
+    fn server() -> (Server, Receiver<RomRequest>) {
+        let mut emulator = Emulator::new();
+        // 0x200: add 1 to V0, 0x202: jump back
+        emulator.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let (roms, requests) = mpsc::channel();
+        (Server::new(Arc::new(Mutex::new(emulator)), roms), requests)
+    }
+
+    #[test]
+    fn step_count_is_limited() {
+        let (server, _) = server();
+        let registers = server.call("step", &json!({ "count": 3 })).ok().unwrap();
+        assert_eq!(registers["v"][0], 2);
+
+        let error = server
+            .call("step", &json!({ "count": Server::MAX_STEPS + 1 }))
+            .err()
+            .unwrap();
+        assert_eq!(error.code, RpcError::INVALID_PARAMS);
+    }
+
+    #[test]
+    fn states_go_back_to_where_they_were_saved() {
+        let (server, _) = server();
+        server.call("step", &json!({ "count": 2 })).ok().unwrap();
+        server
+            .call("save_state", &json!({ "slot": "a" }))
+            .ok()
+            .unwrap();
+        server.call("step", &json!({ "count": 4 })).ok().unwrap();
+        server
+            .call("load_state", &json!({ "slot": "a" }))
+            .ok()
+            .unwrap();
+
+        let registers = server.call("registers", &Value::Null).ok().unwrap();
+        assert_eq!(
+            (registers["pc"].clone(), registers["v"][0].clone()),
+            (json!(0x200), json!(1))
+        );
+        let error = server
+            .call("load_state", &json!({ "slot": "b" }))
+            .err()
+            .unwrap();
+        assert_eq!(error.code, RpcError::FAILED);
+    }
+
+    #[test]
+    fn roms_are_loaded_by_the_application() {
+        let (server, requests) = server();
+        let application = thread::spawn(move || {
+            let mut paused = Vec::new();
+            for request in requests.iter().take(2) {
+                let result = if request.path == Path::new("game.ch8") {
+                    Ok(())
+                } else {
+                    Err("not found".to_string())
+                };
+                paused.push(request.paused);
+                request.accept().unwrap().send(result).unwrap();
+            }
+            paused
+        });
+
+        let params = json!({ "path": "game.ch8", "paused": true });
+        assert!(server.call("load_rom", &params).is_ok());
+        let error = server
+            .call("load_rom", &json!({ "path": "missing.ch8" }))
+            .err()
+            .unwrap();
+        assert_eq!(error.message, "not found");
+        assert_eq!(application.join().unwrap(), vec![true, false]);
+    }
+
+    #[test]
+    fn roms_not_loaded_in_time_are_withdrawn() {
+        let (mut server, requests) = server();
+        server.load_timeout = Duration::from_millis(10);
+        let error = server
+            .call("load_rom", &json!({ "path": "game.ch8" }))
+            .err()
+            .unwrap();
+        assert_eq!(error.code, RpcError::FAILED);
+        // The application only gets to it after the client gave up
+        assert!(requests.try_recv().unwrap().accept().is_none());
+    }
+}
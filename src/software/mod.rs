@@ -0,0 +1,316 @@
+use crate::application::display_filter::FilterState;
+use crate::application::emu_thread::{EmulatorThread, Input};
+use crate::cli::Options;
+use crate::config::settings::Settings;
+use crate::config::Config;
+use crate::emulator::chip8::Emulator;
+use crate::emulator::keyboard;
+use crate::emulator::known::RomSettings;
+use crate::emulator::screen::Screen;
+use crate::library::rom::RomInfo;
+use crate::library::RomLibrary;
+use minifb::{Key, KeyRepeat, Scale, ScaleMode, Window, WindowOptions};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A plain window for machines where wgpu finds no graphics adapter. The display is drawn into
+/// a pixel buffer on the CPU, and everything is controlled with the keyboard.
+pub struct SoftwareApp {
+    emulator: EmulatorThread,
+    library: RomLibrary,
+    settings: Settings,
+    overrides: RomSettings, // settings from the command line, used over a ROM's recommendations
+    rom: Option<RomInfo>,
+    screen: Screen, // the last frame received from the emulator
+    filter: FilterState,
+    data: Vec<u8>,                     // RGBA pixels as the display filter left them
+    buffer: Vec<u32>,                  // the same pixels in the 0RGB format of minifb
+    redraw: bool,                      // redraw every pixel, e.g. after loading a ROM
+    held: Vec<char>,                   // keyboard characters held down in the last frame
+    dialog: Option<Receiver<PathBuf>>, // ROM picked in an open file dialog
+}
+
+impl SoftwareApp {
+    const FRAME: Duration = Duration::from_micros(16_600);
+
+    pub fn new(options: Options) -> SoftwareApp {
+        let settings = Settings::load();
+        let mut emulator = Emulator::new();
+        emulator.set_seed(options.seed);
+
+        let mut app = SoftwareApp {
+            emulator: EmulatorThread::spawn(emulator),
+            library: RomLibrary::new(RomLibrary::search_paths(&options.rom_dirs, &Config::load())),
+            filter: FilterState::new(
+                Screen::WIDTH * Screen::HEIGHT,
+                settings.display_filter,
+                settings.decay,
+            ),
+            settings,
+            overrides: options.settings,
+            rom: None,
+            screen: Screen::new(),
+            data: vec![0; Screen::WIDTH * Screen::HEIGHT * 4],
+            buffer: vec![0; Screen::WIDTH * Screen::HEIGHT],
+            redraw: true,
+            held: Vec::new(),
+            dialog: None,
+        };
+
+        if let Some(rom) = options.rom {
+            app.start_rom(&rom);
+            if options.paused {
+                app.emulator.lock().pause = true;
+            }
+        } else if let Some(rom) = app.settings.last_rom.clone() {
+            if rom.is_file() {
+                app.start_rom(&rom);
+                app.emulator.lock().pause = true;
+            }
+        }
+
+        app
+    }
+
+    fn start_rom(&mut self, path: &Path) {
+        let path = &fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let rom = match self.library.find(path) {
+            Some(rom) => rom.clone(),
+            None => match RomInfo::from_path(path) {
+                Ok(rom) => rom,
+                Err(e) => {
+                    eprintln!("Failed to load ROM: '{0}', [ERROR]: {1}", path.display(), e);
+                    return;
+                }
+            },
+        };
+
+        // A ROM that fails to load leaves the running one and its settings alone
+        let contents = match fs::read(&rom.path).and_then(|contents| {
+            Emulator::check_rom(&contents)?;
+            Ok(contents)
+        }) {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!(
+                    "Failed to load ROM: '{0}', [ERROR]: {1}",
+                    rom.path.display(),
+                    e
+                );
+                return;
+            }
+        };
+
+        let mut emulator = self.emulator.lock();
+        let settings = self.overrides.or(rom.recommended.unwrap_or_default());
+        self.settings.configure(&mut emulator, settings);
+        if let Err(e) = emulator.load_rom(&contents) {
+            eprintln!(
+                "Failed to load ROM: '{0}', [ERROR]: {1}",
+                rom.path.display(),
+                e
+            );
+            return;
+        }
+        drop(emulator);
+
+        self.settings.last_rom = Some(rom.path.clone());
+        self.rom = Some(rom);
+        self.held.clear();
+    }
+
+    // Asks for a ROM in the file dialog of the desktop. The dialog blocks until it is closed,
+    // so it runs on another thread and the window keeps running meanwhile
+    fn open_dialog(&mut self) {
+        if self.dialog.is_some() {
+            return;
+        }
+        let folder = self
+            .rom
+            .as_ref()
+            .and_then(|rom| rom.path.parent())
+            .map(|folder| format!("{}/", folder.display()))
+            .unwrap_or_default();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let patterns: Vec<String> = RomLibrary::EXTENSIONS
+                .iter()
+                .map(|extension| format!("*.{}", extension))
+                .collect();
+            let patterns: Vec<&str> = patterns.iter().map(String::as_str).collect();
+            let filter = Some((patterns.as_slice(), "CHIP-8 ROMs"));
+            // Cancelling the dialog drops the sender, which gives up waiting for it
+            if let Some(path) = tinyfiledialogs::open_file_dialog("Load ROM", &folder, filter) {
+                let _ = sender.send(PathBuf::from(path));
+            }
+        });
+        self.dialog = Some(receiver);
+    }
+
+    fn check_dialog(&mut self) {
+        let result = match &self.dialog {
+            Some(dialog) => dialog.try_recv(),
+            None => return,
+        };
+        match result {
+            Ok(path) => {
+                self.dialog = None;
+                self.start_rom(&path);
+            }
+            Err(TryRecvError::Disconnected) => self.dialog = None,
+            Err(TryRecvError::Empty) => (),
+        }
+    }
+
+    // Starts the ROM `offset` places away from the current one in the library
+    fn switch_rom(&mut self, offset: isize) {
+        let roms = self.library.roms();
+        if roms.is_empty() {
+            return;
+        }
+        let current = self
+            .rom
+            .as_ref()
+            .and_then(|rom| roms.iter().position(|other| other.path == rom.path));
+        let index = match current {
+            Some(index) => (index as isize + offset).rem_euclid(roms.len() as isize) as usize,
+            None => 0,
+        };
+        let path = roms[index].path.clone();
+        self.start_rom(&path);
+    }
+
+    /// Opens the window and runs until it is closed.
+    pub fn run(&mut self) -> io::Result<()> {
+        let options = WindowOptions {
+            resize: true,
+            scale: Scale::X16,
+            scale_mode: ScaleMode::AspectRatioStretch,
+            ..WindowOptions::default()
+        };
+        let mut window = Window::new(&self.title(), Screen::WIDTH, Screen::HEIGHT, options)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        window.limit_update_rate(Some(SoftwareApp::FRAME));
+
+        let mut title = self.title();
+        let mut last_frame = Instant::now();
+        while window.is_open() {
+            for key in window.get_keys_pressed(KeyRepeat::No) {
+                match key {
+                    Key::Escape => {
+                        self.quit();
+                        return Ok(());
+                    }
+                    Key::F5 => {
+                        let mut emulator = self.emulator.lock();
+                        emulator.pause = !emulator.pause;
+                    }
+                    Key::F8 => {
+                        if let Some(rom) = self.rom.clone() {
+                            self.start_rom(&rom.path);
+                        }
+                    }
+                    Key::Equal | Key::NumPadPlus => self.change_speed(2.0),
+                    Key::Minus | Key::NumPadMinus => self.change_speed(0.5),
+                    Key::PageDown => self.switch_rom(1),
+                    Key::PageUp => self.switch_rom(-1),
+                    Key::F3 => self.open_dialog(),
+                    _ => (),
+                }
+            }
+            self.check_dialog();
+            self.set_held_keys(&window.get_keys());
+
+            let now = Instant::now();
+            self.update_buffer((now - last_frame).as_secs_f32());
+            last_frame = now;
+
+            let current = self.title();
+            if current != title {
+                window.set_title(&current);
+                title = current;
+            }
+            window
+                .update_with_buffer(&self.buffer, Screen::WIDTH, Screen::HEIGHT)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        }
+
+        self.quit();
+        Ok(())
+    }
+
+    fn quit(&mut self) {
+        self.emulator.stop();
+        self.settings.save();
+    }
+
+    fn change_speed(&self, factor: f32) {
+        let mut emulator = self.emulator.lock();
+        emulator.speed = (emulator.speed * factor).clamp(Emulator::MIN_SPEED, Emulator::MAX_SPEED);
+    }
+
+    // The window has no room for controls, so the state is shown in its title
+    fn title(&self) -> String {
+        let mut title = match &self.rom {
+            Some(rom) => format!("Chippus - {}", rom.title),
+            None => "Chippus - PgUp/PgDn or F3 to pick a ROM".to_string(),
+        };
+        if self.dialog.is_some() {
+            title.push_str(" (pick a ROM in the dialog)");
+        }
+        let emulator = self.emulator.lock();
+        if emulator.pause {
+            title.push_str(" (paused)");
+        }
+        if (emulator.speed - 1.0).abs() > f32::EPSILON {
+            title.push_str(&format!(" ({}x)", emulator.speed));
+        }
+        title
+    }
+
+    // Tells the emulator about the keys pressed or let go since the last frame
+    fn set_held_keys(&mut self, keys: &[Key]) {
+        let held: Vec<char> = keys
+            .iter()
+            // The keys are named after the characters, e.g. `Key1` and `Q`
+            .filter_map(|key| keyboard::key_char(&format!("{:?}", key)))
+            .collect();
+        for c in held.iter().filter(|c| !self.held.contains(c)) {
+            self.emulator.send(Input::Key(*c, true));
+        }
+        for c in self.held.iter().filter(|c| !held.contains(c)) {
+            self.emulator.send(Input::Key(*c, false));
+        }
+        self.held = held;
+    }
+
+    fn update_buffer(&mut self, dt: f32) {
+        let mut rows = 0;
+        if let Some(frame) = self.emulator.take_frame() {
+            self.screen = frame.screen;
+            rows = frame.dirty_rows;
+        }
+        if self.redraw || self.filter.is_animated() {
+            self.redraw = false;
+            rows = Screen::ALL_ROWS;
+        }
+        if rows == 0 {
+            return;
+        }
+
+        self.filter.apply(
+            &self.screen,
+            rows,
+            &self.settings.palette,
+            &mut self.data,
+            dt,
+        );
+        for (pixel, rgba) in self.buffer.iter_mut().zip(self.data.chunks(4)) {
+            *pixel = u32::from_be_bytes([0, rgba[0], rgba[1], rgba[2]]);
+        }
+    }
+}
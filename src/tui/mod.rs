@@ -0,0 +1,268 @@
+use crate::application::emu_thread::{EmulatorThread, Input};
+use crate::cli::Options;
+use crate::config::settings::Settings;
+use crate::emulator::chip8::Emulator;
+use crate::emulator::keyboard;
+use crate::emulator::screen::Screen;
+use crate::library::rom::RomInfo;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Puts the terminal back the way it was when dropped, so an error or a panic on the way out
+// does not leave it in raw mode
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter(out: &mut impl Write) -> io::Result<RawTerminal> {
+        terminal::enable_raw_mode()?;
+        let terminal = RawTerminal;
+        execute!(out, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+        Ok(terminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        // Every step is tried, there is nothing left to do about the ones that fail
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Runs a ROM in the terminal, for machines without a display or GPU. The screen is drawn with
+/// half blocks (64x16 characters) or braille (32x8 characters), with the registers next to it.
+pub struct TerminalApp {
+    emulator: EmulatorThread,
+    rom: RomInfo,
+    screen: Screen,
+    braille: bool,
+    redraw: bool,               // draw the screen again, even if it did not change
+    held: Vec<(char, Instant)>, // pressed keys and when to let go of them
+}
+
+impl TerminalApp {
+    const FRAME: Duration = Duration::from_millis(16);
+    // Terminals only report presses, so a key counts as held for a while after each press.
+    // The first press is held longer to bridge the delay before the terminal repeats it.
+    const FIRST_HOLD: Duration = Duration::from_millis(500);
+    const REPEAT_HOLD: Duration = Duration::from_millis(100);
+    const PANEL_X: u16 = Screen::WIDTH as u16 + 2; // column of the side panel
+
+    pub fn new(options: &Options, path: &Path) -> io::Result<TerminalApp> {
+        let rom = RomInfo::from_path(path)?;
+        let mut emulator = Emulator::new();
+        emulator.set_seed(options.seed);
+        Settings::load().configure(
+            &mut emulator,
+            options.settings.or(rom.recommended.unwrap_or_default()),
+        );
+        emulator.load_rom(&fs::read(path)?)?;
+        emulator.pause = options.paused;
+
+        Ok(TerminalApp {
+            emulator: EmulatorThread::spawn(emulator),
+            rom,
+            screen: Screen::new(),
+            braille: false,
+            redraw: true,
+            held: Vec::new(),
+        })
+    }
+
+    /// Takes over the terminal until the user quits, restoring it afterwards.
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        let terminal = RawTerminal::enter(&mut stdout)?;
+
+        let result = self.main_loop(&mut stdout);
+
+        drop(terminal);
+        self.emulator.stop();
+        result
+    }
+
+    fn main_loop(&mut self, out: &mut impl Write) -> io::Result<()> {
+        loop {
+            let deadline = Instant::now() + TerminalApp::FRAME;
+            while event::poll(deadline.saturating_duration_since(Instant::now()))? {
+                match event::read()? {
+                    Event::Key(key) if !self.handle_key(key) => return Ok(()),
+                    Event::Resize(..) => {
+                        queue!(out, Clear(ClearType::All))?;
+                        self.redraw = true;
+                    }
+                    _ => (),
+                }
+            }
+            self.release_keys();
+
+            if let Some(frame) = self.emulator.take_frame() {
+                self.screen = frame.screen;
+                self.redraw = true;
+            }
+            if self.redraw {
+                self.draw_screen(out)?;
+                self.redraw = false;
+            }
+            self.draw_panel(out)?;
+            out.flush()?;
+        }
+    }
+
+    // Returns false if the user wants to quit
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::F(2) => {
+                self.braille = !self.braille;
+                self.redraw = true;
+            }
+            KeyCode::F(5) => {
+                let mut emulator = self.emulator.lock();
+                emulator.pause = !emulator.pause;
+            }
+            KeyCode::F(6) => self.emulator.lock().step(),
+            KeyCode::Char(c) => {
+                if let Some(c) = keyboard::key_char(c.encode_utf8(&mut [0; 4])) {
+                    self.press(c);
+                }
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn press(&mut self, c: char) {
+        let now = Instant::now();
+        match self.held.iter_mut().find(|(key, _)| *key == c) {
+            Some((_, release)) => *release = now + TerminalApp::REPEAT_HOLD,
+            None => {
+                self.held.push((c, now + TerminalApp::FIRST_HOLD));
+                self.emulator.send(Input::Key(c, true));
+            }
+        }
+    }
+
+    fn release_keys(&mut self) {
+        let now = Instant::now();
+        let emulator = &self.emulator;
+        self.held.retain(|(c, release)| {
+            if *release > now {
+                return true;
+            }
+            emulator.send(Input::Key(*c, false));
+            false
+        });
+    }
+
+    fn draw_screen(&self, out: &mut impl Write) -> io::Result<()> {
+        let lines = if self.braille {
+            TerminalApp::braille_lines(&self.screen)
+        } else {
+            TerminalApp::block_lines(&self.screen)
+        };
+        // Blank out the rest of the half block screen, which is larger than the braille one
+        for y in 0..Screen::HEIGHT / 2 {
+            let line = lines.get(y).map_or("", String::as_str);
+            let padding = Screen::WIDTH - line.chars().count();
+            queue!(
+                out,
+                MoveTo(0, y as u16),
+                Print(line),
+                Print(" ".repeat(padding))
+            )?;
+        }
+        Ok(())
+    }
+
+    // Two pixels above each other per character
+    fn block_lines(screen: &Screen) -> Vec<String> {
+        (0..Screen::HEIGHT)
+            .step_by(2)
+            .map(|y| {
+                (0..Screen::WIDTH)
+                    .map(
+                        |x| match (screen.get_pixel(x, y), screen.get_pixel(x, y + 1)) {
+                            (1, 1) => '█',
+                            (1, _) => '▀',
+                            (_, 1) => '▄',
+                            _ => ' ',
+                        },
+                    )
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Two by four pixels per character, each pixel one of the braille dots
+    fn braille_lines(screen: &Screen) -> Vec<String> {
+        // Bit of the dot for each pixel of a cell, by row and then column
+        const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+        (0..Screen::HEIGHT)
+            .step_by(4)
+            .map(|y| {
+                (0..Screen::WIDTH)
+                    .step_by(2)
+                    .map(|x| {
+                        let mut dots = 0;
+                        for (dy, row) in DOTS.iter().enumerate() {
+                            for (dx, dot) in row.iter().enumerate() {
+                                if screen.get_pixel(x + dx, y + dy) == 1 {
+                                    dots |= dot;
+                                }
+                            }
+                        }
+                        std::char::from_u32(0x2800 + dots).unwrap_or(' ')
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn draw_panel(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut lines = vec![self.rom.title.clone(), String::new()];
+        {
+            let emulator = self.emulator.lock();
+            lines.push(format!(
+                "PC: {:#05X}  I: {:#05X}  {}",
+                emulator.pc,
+                emulator.i,
+                if emulator.pause { "PAUSED" } else { "RUNNING" }
+            ));
+            for (n, values) in emulator.v.chunks(4).enumerate() {
+                let registers: Vec<String> = values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| format!("V{:X}: {:02X}", n * 4 + i, v))
+                    .collect();
+                lines.push(registers.join("  "));
+            }
+            lines.push(format!(
+                "Delay: {:3}  Sound: {:3}",
+                emulator.delay_timer, emulator.sound_timer
+            ));
+            let stack: Vec<String> = emulator.stack.iter().map(|v| format!("{:X}", v)).collect();
+            lines.push(format!("Stack: {}", stack.join(" ")));
+        }
+        lines.push(String::new());
+        lines.push("F2 blocks/braille  F5 pause  F6 step  Esc quit".to_string());
+
+        for (y, line) in lines.iter().enumerate() {
+            queue!(
+                out,
+                MoveTo(TerminalApp::PANEL_X, y as u16),
+                Clear(ClearType::UntilNewLine),
+                Print(line)
+            )?;
+        }
+        Ok(())
+    }
+}
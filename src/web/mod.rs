@@ -0,0 +1,135 @@
+use crate::emulator::chip8::Emulator;
+use crate::emulator::quirks::{QuirkPreset, Quirks};
+use crate::emulator::screen::Screen;
+use wasm_bindgen::prelude::*;
+
+/// The emulator as JavaScript sees it. The page in `web/` feeds it time and key presses and
+/// draws the pixels it hands back on a canvas.
+#[wasm_bindgen]
+pub struct WebEmulator {
+    emulator: Emulator,
+    colors: [[u8; 4]; 2], // RGBA of pixels that are off and on
+}
+
+#[wasm_bindgen]
+impl WebEmulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WebEmulator {
+        WebEmulator {
+            emulator: Emulator::new(),
+            colors: [[0x0A, 0x17, 0x12, 0xFF], [0x33, 0xFF, 0x66, 0xFF]],
+        }
+    }
+
+    pub fn width() -> usize {
+        Screen::WIDTH
+    }
+
+    pub fn height() -> usize {
+        Screen::HEIGHT
+    }
+
+    /// Loads a ROM and starts running it.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsValue> {
+        self.emulator
+            .load_rom(rom)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Picks the quirks by preset name, e.g. "schip".
+    pub fn set_quirks(&mut self, preset: &str) -> Result<(), JsValue> {
+        let preset: QuirkPreset = preset.parse().map_err(|e: String| JsValue::from_str(&e))?;
+        self.emulator.quirks = Quirks::from_preset(preset);
+        Ok(())
+    }
+
+    pub fn set_clock_speed(&mut self, hz: u32) {
+        self.emulator.clock_speed = hz;
+    }
+
+    /// Sets the colors of pixels that are off and on, as 0xRRGGBB.
+    pub fn set_colors(&mut self, off: u32, on: u32) {
+        for (color, rgb) in self.colors.iter_mut().zip([off, on].iter()) {
+            let [_, r, g, b] = rgb.to_be_bytes();
+            *color = [r, g, b, 0xFF];
+        }
+        self.emulator.screen.set_dirty(true);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.emulator.pause
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.emulator.pause = paused;
+    }
+
+    /// Runs the emulator for `dt` seconds.
+    pub fn run(&mut self, dt: f32) {
+        self.emulator.execute_cycle(dt);
+    }
+
+    /// Presses or releases the key bound to `key`, a `KeyboardEvent.key` value. Returns false
+    /// if no CHIP-8 key is bound to it, so the page can leave the event to the browser.
+    pub fn set_key(&mut self, key: &str, pressed: bool) -> bool {
+        let mut chars = key.chars();
+        let key = match (chars.next(), chars.next()) {
+            (Some(c), None) => self.emulator.keyboard.map_key(c),
+            _ => None,
+        };
+        if let Some(key) = key {
+            self.emulator.keyboard.set(key, pressed);
+        }
+        key.is_some()
+    }
+
+    /// Whether the sound timer is running, i.e. the beep should be heard.
+    pub fn is_beeping(&self) -> bool {
+        self.emulator.sound_timer > 0
+    }
+
+    /// Returns the screen as RGBA pixels for an `ImageData`, or nothing if it did not change
+    /// since the last call.
+    pub fn take_frame(&mut self) -> Option<Vec<u8>> {
+        let screen = &mut self.emulator.screen;
+        if screen.take_dirty_rows() == 0 {
+            return None;
+        }
+
+        let mut pixels = Vec::with_capacity(Screen::WIDTH * Screen::HEIGHT * 4);
+        for y in 0..Screen::HEIGHT {
+            for x in 0..Screen::WIDTH {
+                pixels.extend_from_slice(&self.colors[screen.get_pixel(x, y) as usize]);
+            }
+        }
+        Some(pixels)
+    }
+}
+
+impl Default for WebEmulator {
+    fn default() -> WebEmulator {
+        WebEmulator::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    fn runs_a_rom_and_takes_a_frame() {
+        let mut web = WebEmulator::new();
+        web.set_colors(0x000000, 0xFFFFFF);
+        // Draws the font sprite of 0 in the top left corner, then loops
+        web.load_rom(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06])
+            .unwrap();
+        web.run(0.1);
+
+        let frame = web.take_frame().unwrap();
+        assert_eq!(frame.len(), Screen::WIDTH * Screen::HEIGHT * 4);
+        assert_eq!(frame[..4], [0xFF, 0xFF, 0xFF, 0xFF]); // the pixel at 0, 0 is on
+        assert_eq!(frame[16..20], [0x00, 0x00, 0x00, 0xFF]); // the one at 4, 0 is off
+        assert!(web.take_frame().is_none());
+    }
+}
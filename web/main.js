@@ -0,0 +1,115 @@
+// Browser frontend for Chippus. Build the emulator core with
+// `wasm-pack build --target web --out-dir web/pkg` first, then serve this directory.
+//
+// URL parameters: rom (URL of a ROM to start), quirks (e.g. "schip") and speed (clock in Hz).
+import init, { WebEmulator } from "./pkg/chip8_emulator.js";
+
+// Square wave beep played while the sound timer runs. Browsers only allow audio after the
+// user did something on the page, so it is started on the first key press or click.
+class Beeper {
+  constructor() {
+    this.context = null;
+    this.gain = null;
+  }
+
+  start() {
+    if (this.context) {
+      return;
+    }
+    this.context = new AudioContext();
+    const oscillator = this.context.createOscillator();
+    oscillator.type = "square";
+    oscillator.frequency.value = 440;
+    this.gain = this.context.createGain();
+    this.gain.gain.value = 0;
+    oscillator.connect(this.gain).connect(this.context.destination);
+    oscillator.start();
+  }
+
+  set(on) {
+    if (this.gain) {
+      this.gain.gain.setTargetAtTime(on ? 0.1 : 0, this.context.currentTime, 0.005);
+    }
+  }
+}
+
+async function main() {
+  await init();
+  const emulator = new WebEmulator();
+  const beeper = new Beeper();
+  const canvas = document.getElementById("screen");
+  const context = canvas.getContext("2d");
+  const status = document.getElementById("status");
+  const pause = document.getElementById("pause");
+  const params = new URLSearchParams(window.location.search);
+
+  if (params.has("quirks")) {
+    emulator.set_quirks(params.get("quirks"));
+  }
+  if (params.has("speed")) {
+    emulator.set_clock_speed(parseInt(params.get("speed"), 10));
+  }
+
+  const loadRom = (name, bytes) => {
+    try {
+      emulator.load_rom(bytes);
+      status.textContent = name;
+    } catch (e) {
+      status.textContent = `Failed to load '${name}': ${e}`;
+    }
+  };
+
+  if (params.has("rom")) {
+    const url = params.get("rom");
+    fetch(url)
+      .then((response) => {
+        if (!response.ok) {
+          throw new Error(response.statusText);
+        }
+        return response.arrayBuffer();
+      })
+      .then((buffer) => loadRom(url, new Uint8Array(buffer)))
+      .catch((e) => (status.textContent = `Failed to load '${url}': ${e}`));
+  }
+
+  document.getElementById("rom-file").addEventListener("change", async (event) => {
+    const file = event.target.files[0];
+    if (file) {
+      loadRom(file.name, new Uint8Array(await file.arrayBuffer()));
+    }
+  });
+
+  pause.addEventListener("click", () => {
+    beeper.start();
+    emulator.set_paused(!emulator.is_paused());
+    pause.textContent = emulator.is_paused() ? "Resume" : "Pause";
+  });
+
+  for (const [type, pressed] of [["keydown", true], ["keyup", false]]) {
+    window.addEventListener(type, (event) => {
+      beeper.start();
+      if (emulator.set_key(event.key, pressed)) {
+        event.preventDefault();
+      }
+    });
+  }
+
+  let lastTime = performance.now();
+  const frame = (time) => {
+    // Don't try to catch up after the tab was in the background
+    const dt = Math.min((time - lastTime) / 1000, 0.1);
+    lastTime = time;
+
+    emulator.run(dt);
+    const pixels = emulator.take_frame();
+    if (pixels) {
+      const image = new ImageData(new Uint8ClampedArray(pixels.buffer), WebEmulator.width(), WebEmulator.height());
+      context.putImageData(image, 0, 0);
+    }
+    beeper.set(emulator.is_beeping() && !emulator.is_paused());
+    requestAnimationFrame(frame);
+  };
+  requestAnimationFrame(frame);
+}
+
+main();